@@ -0,0 +1,696 @@
+//! Bounded-memory tile cache.
+//!
+//! Every cached tile is written to disk once, under `cache_dir`. A small
+//! in-memory "hot set" keeps the most-requested, smaller tiles as `Bytes` so
+//! common cases skip a disk round-trip, but anything large is always served
+//! by streaming the on-disk file in fixed-size chunks. This keeps peak
+//! memory proportional to `concurrent connections * chunk size` rather than
+//! to the size of the tiles themselves.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use lru::LruCache;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tokio_util::io::ReaderStream;
+use tracing::{debug, warn};
+
+/// Tiles no larger than this are kept in the in-memory hot set as well as on
+/// disk, so repeat requests for small, popular files avoid a disk read.
+const HOT_SET_MAX_ENTRY_BYTES: u64 = 256 * 1024;
+/// Total bytes the in-memory hot set is allowed to hold across all entries.
+const HOT_SET_BUDGET_BYTES: u64 = 64 * 1024 * 1024;
+/// Upper bound on the number of on-disk index entries tracked in memory,
+/// independent of the byte-size eviction below: a safety net against
+/// unbounded map growth when no `disk_max_bytes` budget is configured.
+/// Entries dropped only for exceeding this count are *not* deleted from
+/// disk (same as before byte-size eviction existed), so they can still be
+/// picked up by [`TileCache::read_orphaned`].
+const DISK_CACHE_CAPACITY: usize = 65536;
+/// Upper bound on the number of in-flight sparse-range objects tracked at
+/// once, mirroring `DISK_CACHE_CAPACITY`'s role for the disk index: a safety
+/// net against unbounded map growth from many distinct never-completed
+/// `cache_key`s, independent of the byte-budget eviction below.
+const SPARSE_CACHE_CAPACITY: usize = 1024;
+/// Total bytes of partially-fetched range data the sparse tracker is
+/// allowed to hold across all in-flight objects, mirroring
+/// `HOT_SET_BUDGET_BYTES`. Bounds the case of few `cache_key`s each
+/// accumulating many small, disjoint ranges that never complete.
+const SPARSE_BUDGET_BYTES: u64 = 64 * 1024 * 1024;
+/// Chunk size used when streaming a tile off disk.
+pub const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+/// Name of the manifest file persisting the disk index across restarts, so a
+/// restarted node warms from disk instead of re-fetching everything from
+/// upstream.
+const INDEX_MANIFEST_FILE_NAME: &str = ".index";
+
+#[derive(Clone)]
+struct HotEntry {
+    bytes: Bytes,
+    etag: String,
+    modified: SystemTime,
+}
+
+struct DiskEntry {
+    path: PathBuf,
+    len: u64,
+    etag: String,
+    modified: SystemTime,
+}
+
+pub struct TileCache {
+    cache_dir: PathBuf,
+    disk_index: Mutex<LruCache<String, DiskEntry>>,
+    /// Total bytes of entries currently tracked in `disk_index`.
+    disk_used_bytes: Mutex<u64>,
+    /// When set, `put` evicts the least-recently-used disk entries (and
+    /// deletes their files) until total disk usage is back under this
+    /// budget.
+    disk_max_bytes: Option<u64>,
+    hot: Mutex<LruCache<String, HotEntry>>,
+    hot_used_bytes: Mutex<u64>,
+    /// Objects currently being fetched one byte range at a time, keyed by
+    /// cache key, for requests that arrive before the full object has been
+    /// cached. Entries are removed once every byte has been seen, and are
+    /// also subject to capacity (`SPARSE_CACHE_CAPACITY`) and byte-budget
+    /// (`SPARSE_BUDGET_BYTES`) eviction like the hot set and disk index, so
+    /// a client that never completes a range (or opens many distinct ones)
+    /// can't grow this map without bound.
+    sparse: Mutex<LruCache<String, SparseObject>>,
+    /// Total bytes of chunk data currently tracked across all `sparse` entries.
+    sparse_used_bytes: Mutex<u64>,
+}
+
+/// The bytes of an object seen so far via range-forwarded upstream fetches,
+/// kept as an ordered set of chunks that are merged as they start to
+/// overlap or touch, so that sequential range reads from the same client
+/// coalesce into one contiguous span instead of being tracked separately.
+struct SparseObject {
+    known_len: Option<u64>,
+    chunks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl SparseObject {
+    fn new() -> Self {
+        SparseObject {
+            known_len: None,
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    fn set_known_len(&mut self, len: u64) {
+        self.known_len = Some(len);
+    }
+
+    /// Inserts `bytes` at `offset`, merging it with any chunk it overlaps
+    /// or directly abuts.
+    fn insert(&mut self, offset: u64, bytes: Vec<u8>) {
+        let mut merged_start = offset;
+        let mut merged = bytes;
+
+        if let Some((&prev_start, prev_bytes)) = self.chunks.range(..=merged_start).next_back() {
+            let prev_end = prev_start + prev_bytes.len() as u64;
+            if prev_end >= merged_start {
+                let prefix_len = (merged_start - prev_start) as usize;
+                let new_end = merged_start + merged.len() as u64;
+                let mut combined = prev_bytes[..prefix_len.min(prev_bytes.len())].to_vec();
+                combined.extend_from_slice(&merged);
+                // `prev_bytes` may extend past the newly-inserted range (it
+                // fully contains a sub-range being re-inserted); keep that
+                // tail instead of truncating it away.
+                if prev_end > new_end {
+                    let tail_start = (new_end - prev_start) as usize;
+                    combined.extend_from_slice(&prev_bytes[tail_start.min(prev_bytes.len())..]);
+                }
+                merged = combined;
+                merged_start = prev_start;
+                self.chunks.remove(&prev_start);
+            }
+        }
+
+        let merged_end = merged_start + merged.len() as u64;
+        let overlapping: Vec<u64> = self
+            .chunks
+            .range(merged_start..)
+            .take_while(|(&start, _)| start <= merged_end)
+            .map(|(&start, _)| start)
+            .collect();
+        for start in overlapping {
+            let next_bytes = self.chunks.remove(&start).unwrap();
+            let next_end = start + next_bytes.len() as u64;
+            if next_end > merged_end {
+                let overlap = (merged_end - start) as usize;
+                merged.extend_from_slice(&next_bytes[overlap.min(next_bytes.len())..]);
+            }
+        }
+
+        self.chunks.insert(merged_start, merged);
+    }
+
+    /// Returns `[start, end)` only if a single coalesced chunk fully covers it.
+    fn get_range(&self, start: u64, end: u64) -> Option<Vec<u8>> {
+        let (&chunk_start, chunk) = self.chunks.range(..=start).next_back()?;
+        let chunk_end = chunk_start + chunk.len() as u64;
+        if chunk_start <= start && chunk_end >= end {
+            let offset = (start - chunk_start) as usize;
+            let len = (end - start) as usize;
+            Some(chunk[offset..offset + len].to_vec())
+        } else {
+            None
+        }
+    }
+
+    /// Total bytes currently held across all chunks, used to account this
+    /// object against the sparse tracker's byte budget.
+    fn byte_len(&self) -> u64 {
+        self.chunks.values().map(|chunk| chunk.len() as u64).sum()
+    }
+
+    /// Returns the full object once every byte from `0` to `known_len` has
+    /// been seen in a single coalesced chunk.
+    fn complete_bytes(&self) -> Option<Vec<u8>> {
+        let known_len = self.known_len?;
+        if known_len == 0 {
+            return Some(Vec::new());
+        }
+        let (&start, chunk) = self.chunks.iter().next()?;
+        (start == 0 && chunk.len() as u64 == known_len).then(|| chunk.clone())
+    }
+}
+
+/// A cached tile, either served straight from memory or streamed off disk.
+pub enum CachedTile {
+    Hot {
+        bytes: Bytes,
+        etag: String,
+        modified: SystemTime,
+    },
+    Disk {
+        path: PathBuf,
+        len: u64,
+        etag: String,
+        modified: SystemTime,
+    },
+}
+
+impl CachedTile {
+    pub fn len(&self) -> u64 {
+        match self {
+            CachedTile::Hot { bytes, .. } => bytes.len() as u64,
+            CachedTile::Disk { len, .. } => *len,
+        }
+    }
+
+    // Satisfies `clippy::len_without_is_empty`; nothing in this binary crate
+    // currently needs to special-case an empty tile, so there's no caller.
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Strong validator for conditional requests (`ETag`/`If-None-Match`).
+    pub fn etag(&self) -> &str {
+        match self {
+            CachedTile::Hot { etag, .. } => etag,
+            CachedTile::Disk { etag, .. } => etag,
+        }
+    }
+
+    /// When this tile was cached, used for `Last-Modified`/`If-Modified-Since`.
+    pub fn modified(&self) -> SystemTime {
+        match self {
+            CachedTile::Hot { modified, .. } => *modified,
+            CachedTile::Disk { modified, .. } => *modified,
+        }
+    }
+
+    /// Produces an `axum::body::Body` covering the byte range `[start, end)`
+    /// of this tile, streaming off disk in `STREAM_CHUNK_BYTES` chunks when
+    /// the tile isn't hot.
+    pub async fn body_for_range(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> std::io::Result<axum::body::Body> {
+        match self {
+            CachedTile::Hot { bytes, .. } => {
+                let slice = bytes.slice(start as usize..end as usize);
+                Ok(axum::body::Body::from(slice))
+            }
+            CachedTile::Disk { path, .. } => {
+                let mut file = tokio::fs::File::open(path).await?;
+                file.seek(std::io::SeekFrom::Start(start)).await?;
+                let limited = file.take(end - start);
+                let stream = ReaderStream::with_capacity(limited, STREAM_CHUNK_BYTES);
+                Ok(axum::body::Body::from_stream(stream))
+            }
+        }
+    }
+}
+
+impl TileCache {
+    /// Creates a cache rooted at `cache_dir`, reloading its index manifest
+    /// (if any) from a previous run so a restarted node warms from disk
+    /// instead of re-fetching everything from upstream. `disk_max_bytes`, if
+    /// set, bounds total disk usage: once exceeded, `put` evicts the
+    /// least-recently-used entries (oldest first) and deletes their files.
+    pub fn new(cache_dir: PathBuf, disk_max_bytes: Option<u64>) -> Self {
+        let mut disk_index = LruCache::new(NonZeroUsize::new(DISK_CACHE_CAPACITY).unwrap());
+        let mut disk_used_bytes = 0u64;
+        for (cache_key, entry) in load_index_manifest(&cache_dir) {
+            disk_used_bytes += entry.len;
+            disk_index.put(cache_key, entry);
+        }
+
+        TileCache {
+            cache_dir,
+            disk_index: Mutex::new(disk_index),
+            disk_used_bytes: Mutex::new(disk_used_bytes),
+            disk_max_bytes,
+            hot: Mutex::new(LruCache::new(NonZeroUsize::new(1024).unwrap())),
+            hot_used_bytes: Mutex::new(0),
+            sparse: Mutex::new(LruCache::new(NonZeroUsize::new(SPARSE_CACHE_CAPACITY).unwrap())),
+            sparse_used_bytes: Mutex::new(0),
+        }
+    }
+
+    /// Returns `[start, end)` and the object's total length if a
+    /// previous range-forwarded fetch already covers the requested span.
+    pub async fn get_sparse_range(
+        &self,
+        cache_key: &str,
+        start: u64,
+        end: u64,
+    ) -> Option<(Vec<u8>, u64)> {
+        let mut sparse = self.sparse.lock().await;
+        let object = sparse.get_mut(cache_key)?;
+        let bytes = object.get_range(start, end)?;
+        Some((bytes, object.known_len?))
+    }
+
+    /// Records a byte range of `cache_key` fetched from upstream, merging it
+    /// with any previously-fetched ranges. If every byte up to `total_len`
+    /// has now been seen, returns the completed object and stops tracking it
+    /// here — the caller should `put` it into the durable cache instead.
+    ///
+    /// Bounded by `SPARSE_CACHE_CAPACITY` and `SPARSE_BUDGET_BYTES`: a new
+    /// `cache_key` past the capacity cap evicts the least-recently-touched
+    /// in-flight object, and once the budget is exceeded the
+    /// least-recently-touched objects are dropped (even one just inserted
+    /// into, if it alone is over budget) until usage is back under it.
+    /// Dropped entries simply have to be re-fetched from upstream on their
+    /// next request — no different from never having been tracked here.
+    pub async fn insert_sparse_range(
+        &self,
+        cache_key: &str,
+        offset: u64,
+        bytes: Vec<u8>,
+        total_len: u64,
+    ) -> Option<Vec<u8>> {
+        let mut sparse = self.sparse.lock().await;
+        let mut used = self.sparse_used_bytes.lock().await;
+
+        let before = sparse.peek(cache_key).map_or(0, SparseObject::byte_len);
+        if sparse.peek(cache_key).is_none()
+            && let Some((_, evicted)) = sparse.push(cache_key.to_string(), SparseObject::new())
+        {
+            *used = used.saturating_sub(evicted.byte_len());
+        }
+        let object = sparse.get_mut(cache_key).expect("just inserted above if absent");
+        object.insert(offset, bytes);
+        object.set_known_len(total_len);
+        let after = object.byte_len();
+        *used = used.saturating_sub(before) + after;
+
+        while *used > SPARSE_BUDGET_BYTES
+            && let Some((_, evicted)) = sparse.pop_lru()
+        {
+            *used = used.saturating_sub(evicted.byte_len());
+        }
+
+        let complete = sparse.get(cache_key).and_then(SparseObject::complete_bytes);
+        if let Some(complete) = complete {
+            if let Some(removed) = sparse.pop(cache_key) {
+                *used = used.saturating_sub(removed.byte_len());
+            }
+            Some(complete)
+        } else {
+            None
+        }
+    }
+
+    /// Reads bytes still on disk at `cache_key`'s hashed-key path,
+    /// even though its disk-index entry (and ETag) may have been evicted by
+    /// the LRU cap, re-deriving the ETag so the caller can revalidate with
+    /// upstream instead of blindly re-downloading an immutable tile.
+    pub async fn read_orphaned(&self, cache_key: &str) -> Option<(Vec<u8>, String)> {
+        let path = self.disk_path(cache_key);
+        let bytes = tokio::fs::read(&path).await.ok()?;
+        let etag = etag_for_body(&bytes);
+        Some((bytes, etag))
+    }
+
+    fn disk_path(&self, cache_key: &str) -> PathBuf {
+        disk_path_in(&self.cache_dir, cache_key)
+    }
+
+    /// Rewrites the index manifest from `disk_index`'s current contents,
+    /// oldest entry first, so reloading it on the next restart reconstructs
+    /// the same recency ordering. Called after every `put` — acceptable
+    /// since disk writes are already the expensive part of caching a tile.
+    async fn persist_index_manifest(
+        &self,
+        disk_index: &LruCache<String, DiskEntry>,
+    ) -> std::io::Result<()> {
+        let mut manifest = String::new();
+        for (cache_key, entry) in disk_index.iter().rev() {
+            let modified_secs = entry
+                .modified
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            manifest.push_str(&format!(
+                "{modified_secs}\t{}\t{}\t{cache_key}\n",
+                entry.len, entry.etag
+            ));
+        }
+
+        let manifest_path = self.cache_dir.join(INDEX_MANIFEST_FILE_NAME);
+        let tmp_path = manifest_path.with_extension(format!("tmp-{}", random_suffix()));
+        tokio::fs::write(&tmp_path, manifest).await?;
+        tokio::fs::rename(&tmp_path, &manifest_path).await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, cache_key: &str) -> Option<CachedTile> {
+        if let Some(entry) = self.hot.lock().await.get(cache_key) {
+            return Some(CachedTile::Hot {
+                bytes: entry.bytes.clone(),
+                etag: entry.etag.clone(),
+                modified: entry.modified,
+            });
+        }
+        let entry = self
+            .disk_index
+            .lock()
+            .await
+            .get(cache_key)
+            .map(|e| (e.path.clone(), e.etag.clone(), e.modified));
+        match entry {
+            Some((path, etag, modified)) => match tokio::fs::metadata(&path).await {
+                Ok(meta) => Some(CachedTile::Disk {
+                    path,
+                    len: meta.len(),
+                    etag,
+                    modified,
+                }),
+                Err(e) => {
+                    warn!("Cached tile {} missing on disk: {}", path.display(), e);
+                    None
+                }
+            },
+            None => None,
+        }
+    }
+
+    /// Stores `body` under `cache_key`: always written to disk, and also
+    /// kept in the in-memory hot set if it's small enough and there's
+    /// budget, evicting older hot entries first. `upstream_etag` is reused
+    /// verbatim if the upstream supplied one; otherwise a strong ETag is
+    /// derived from the body's SHA-256 so identical bytes always validate
+    /// the same way.
+    pub async fn put(
+        &self,
+        cache_key: &str,
+        body: &[u8],
+        upstream_etag: Option<&str>,
+    ) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.cache_dir).await?;
+        let path = self.disk_path(cache_key);
+        let tmp_path = path.with_extension(format!("tmp-{}", random_suffix()));
+        {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            file.write_all(body).await?;
+            file.flush().await?;
+        }
+        tokio::fs::rename(&tmp_path, &path).await?;
+
+        let etag = match upstream_etag {
+            Some(etag) => etag.to_string(),
+            None => etag_for_body(body),
+        };
+        let modified = SystemTime::now();
+
+        {
+            let mut disk_index = self.disk_index.lock().await;
+            let mut used = self.disk_used_bytes.lock().await;
+            // `push`, not `put`: `put` only reports an eviction when it
+            // replaces the same key, so once `disk_index` is past its own
+            // `DISK_CACHE_CAPACITY` cap, a capacity eviction of a *different*
+            // key would go uncounted and `used` would drift upward forever.
+            if let Some((_, evicted)) = disk_index.push(
+                cache_key.to_string(),
+                DiskEntry {
+                    path,
+                    len: body.len() as u64,
+                    etag: etag.clone(),
+                    modified,
+                },
+            ) {
+                *used = used.saturating_sub(evicted.len);
+            }
+            *used += body.len() as u64;
+
+            if let Some(max_bytes) = self.disk_max_bytes {
+                while *used > max_bytes
+                    && let Some((evicted_key, evicted)) = disk_index.pop_lru()
+                {
+                    *used = used.saturating_sub(evicted.len);
+                    // The hot set only mirrors tiles that are on disk, so
+                    // drop its copy of the evicted tile as well.
+                    if let Some(hot_entry) = self.hot.lock().await.pop(&evicted_key) {
+                        let mut hot_used = self.hot_used_bytes.lock().await;
+                        *hot_used = hot_used.saturating_sub(hot_entry.bytes.len() as u64);
+                    }
+                    if let Err(e) = tokio::fs::remove_file(&evicted.path).await {
+                        warn!(
+                            "Failed to delete evicted cache file {}: {}",
+                            evicted.path.display(),
+                            e
+                        );
+                    }
+                }
+            }
+
+            if let Err(e) = self.persist_index_manifest(&disk_index).await {
+                warn!("Failed to persist disk cache index manifest: {}", e);
+            }
+        }
+
+        if (body.len() as u64) <= HOT_SET_MAX_ENTRY_BYTES {
+            self.insert_hot(cache_key, Bytes::copy_from_slice(body), etag, modified)
+                .await;
+        }
+
+        debug!(cache_key, bytes = body.len(), "Cached tile to disk");
+        Ok(())
+    }
+
+    async fn insert_hot(&self, cache_key: &str, bytes: Bytes, etag: String, modified: SystemTime) {
+        let mut hot = self.hot.lock().await;
+        let mut used = self.hot_used_bytes.lock().await;
+        while *used + bytes.len() as u64 > HOT_SET_BUDGET_BYTES
+            && let Some((_, evicted)) = hot.pop_lru()
+        {
+            *used = used.saturating_sub(evicted.bytes.len() as u64);
+        }
+        *used += bytes.len() as u64;
+        // `push`, not `put`: `put` only reports an eviction when it replaces
+        // the same key, so once `hot` is past its own capacity-based cap a
+        // capacity eviction of a *different* key would go uncounted and
+        // `used` would drift upward forever.
+        if let Some((_, evicted)) = hot.push(
+            cache_key.to_string(),
+            HotEntry {
+                bytes,
+                etag,
+                modified,
+            },
+        ) {
+            *used = used.saturating_sub(evicted.bytes.len() as u64);
+        }
+    }
+}
+
+/// Derives the strong ETag used for a body that isn't going through
+/// [`TileCache::put`] (e.g. a precompressed sidecar served straight off
+/// disk), so it still gets a stable validator for conditional requests.
+pub fn etag_for_body(body: &[u8]) -> String {
+    format!("\"{}\"", hex_encode(&Sha256::digest(body)))
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push_str(&format!("{byte:02x}"));
+    }
+    hex
+}
+
+fn disk_path_in(cache_dir: &Path, cache_key: &str) -> PathBuf {
+    let mut hasher = Sha1::new();
+    hasher.update(cache_key.as_bytes());
+    let digest: [u8; 20] = hasher.finalize().into();
+    cache_dir.join(hex_encode(&digest))
+}
+
+/// Loads a previously persisted disk index manifest, oldest entry first (so
+/// replaying it into a fresh `LruCache` in this order reconstructs the same
+/// recency ordering). Entries whose backing file has since disappeared are
+/// skipped. Returns an empty list if there's no manifest yet (fresh cache
+/// directory, or an older version of heliotorrent that never wrote one).
+fn load_index_manifest(cache_dir: &Path) -> Vec<(String, DiskEntry)> {
+    let manifest_path = cache_dir.join(INDEX_MANIFEST_FILE_NAME);
+    let Ok(contents) = std::fs::read_to_string(&manifest_path) else {
+        return Vec::new();
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let modified_secs: u64 = fields.next()?.parse().ok()?;
+            let len: u64 = fields.next()?.parse().ok()?;
+            let etag = fields.next()?.to_string();
+            let cache_key = fields.next()?.to_string();
+
+            let path = disk_path_in(cache_dir, &cache_key);
+            if !path.exists() {
+                return None;
+            }
+
+            let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(modified_secs);
+            Some((
+                cache_key,
+                DiskEntry {
+                    path,
+                    len,
+                    etag,
+                    modified,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn restarted_cache_warms_from_disk() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache = TileCache::new(dir.path().to_path_buf(), None);
+        cache.put("tile/000", b"hello world", None).await.unwrap();
+
+        // A fresh `TileCache` (simulating a restart) should see the tile
+        // without needing any upstream fetch.
+        let restarted = TileCache::new(dir.path().to_path_buf(), None);
+        let tile = restarted.get("tile/000").await.expect("tile should survive restart");
+        assert_eq!(tile.len(), 11);
+    }
+
+    #[tokio::test]
+    async fn disk_budget_evicts_least_recently_used_and_deletes_its_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache = TileCache::new(dir.path().to_path_buf(), Some(12));
+
+        cache.put("a", b"0123456789", None).await.unwrap(); // 10 bytes, fits
+        assert!(cache.get("a").await.is_some());
+
+        cache.put("b", b"0123456789", None).await.unwrap(); // pushes total to 20 > 12
+        assert!(
+            cache.get("a").await.is_none(),
+            "least-recently-used entry should have been evicted"
+        );
+        assert!(!disk_path_in(dir.path(), "a").exists());
+        assert!(cache.get("b").await.is_some());
+    }
+
+    #[test]
+    fn sparse_insert_of_nested_subrange_keeps_enclosing_chunks_tail() {
+        let mut sparse = SparseObject::new();
+        sparse.insert(0, b"0123456789".to_vec());
+
+        // [3, 6) is fully contained within the existing [0, 10) chunk; the
+        // backward merge must not truncate away bytes 6..10 of it.
+        sparse.insert(3, b"345".to_vec());
+
+        assert_eq!(
+            sparse.get_range(0, 10),
+            Some(b"0123456789".to_vec()),
+            "re-inserting a nested sub-range must not drop the enclosing chunk's tail"
+        );
+    }
+
+    #[tokio::test]
+    async fn sparse_range_tracking_evicts_least_recently_used_past_capacity() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache = TileCache::new(dir.path().to_path_buf(), None);
+
+        for i in 0..=SPARSE_CACHE_CAPACITY {
+            let key = format!("tile/{i}");
+            cache.insert_sparse_range(&key, 0, b"x".to_vec(), 10).await;
+        }
+
+        assert!(
+            cache.get_sparse_range("tile/0", 0, 1).await.is_none(),
+            "oldest in-flight sparse object should have been evicted once capacity was exceeded"
+        );
+        assert!(cache
+            .get_sparse_range(&format!("tile/{SPARSE_CACHE_CAPACITY}"), 0, 1)
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn sparse_range_tracking_is_bounded_by_byte_budget() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache = TileCache::new(dir.path().to_path_buf(), None);
+
+        // A single object whose tracked ranges alone exceed the byte
+        // budget must not be allowed to grow the sparse tracker forever.
+        let oversized = vec![0u8; (SPARSE_BUDGET_BYTES + 1) as usize];
+        cache
+            .insert_sparse_range("huge", 0, oversized, SPARSE_BUDGET_BYTES * 2)
+            .await;
+
+        assert!(
+            cache.get_sparse_range("huge", 0, 1).await.is_none(),
+            "an in-flight object over the byte budget on its own must be evicted"
+        );
+    }
+}
+
+fn random_suffix() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let mut hasher = DefaultHasher::new();
+    SystemTime::now().hash(&mut hasher);
+    std::thread::current().id().hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn default_cache_dir(log_data_dir: &Path) -> PathBuf {
+    log_data_dir.join(".cache")
+}
+
+pub type SharedTileCache = Arc<TileCache>;
@@ -0,0 +1,77 @@
+//! Self-signed TLS certificate generation for first-run deployments.
+//!
+//! When `tls_cert`/`tls_key` are absent, or point at files that don't exist
+//! yet, [`ensure_certificate`] generates a self-signed certificate/key pair
+//! via `rcgen` covering the configured hostnames (or `localhost` if none are
+//! given), and persists them under `data_dir` so the same certificate is
+//! reused across restarts instead of being regenerated - and needing to be
+//! re-trusted by clients - on every launch.
+
+use std::path::{Path, PathBuf};
+
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair};
+use tracing::{debug, info, warn};
+
+const CERT_FILE_NAME: &str = "self_signed_cert.pem";
+const KEY_FILE_NAME: &str = "self_signed_key.pem";
+
+/// Resolves the certificate/key paths to use for the TLS listeners:
+/// operator-supplied paths are preferred whenever both are set and exist,
+/// otherwise a self-signed pair is generated (or a previously generated one
+/// is reused) under `data_dir`.
+pub fn ensure_certificate(
+    data_dir: &Path,
+    tls_cert: Option<&str>,
+    tls_key: Option<&str>,
+    hostnames: &[String],
+) -> Result<(PathBuf, PathBuf), Box<dyn std::error::Error>> {
+    if let (Some(cert_path), Some(key_path)) = (tls_cert, tls_key) {
+        let (cert_path, key_path) = (PathBuf::from(cert_path), PathBuf::from(key_path));
+        if cert_path.exists() && key_path.exists() {
+            return Ok((cert_path, key_path));
+        }
+        warn!(
+            "Configured tls_cert/tls_key not found on disk; generating a self-signed certificate instead."
+        );
+    }
+
+    let cert_path = data_dir.join(CERT_FILE_NAME);
+    let key_path = data_dir.join(KEY_FILE_NAME);
+    if cert_path.exists() && key_path.exists() {
+        debug!("Reusing previously generated self-signed certificate.");
+        return Ok((cert_path, key_path));
+    }
+
+    generate_self_signed(&cert_path, &key_path, hostnames)?;
+    Ok((cert_path, key_path))
+}
+
+fn generate_self_signed(
+    cert_path: &Path,
+    key_path: &Path,
+    hostnames: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sans = if hostnames.is_empty() {
+        vec!["localhost".to_string()]
+    } else {
+        hostnames.to_vec()
+    };
+
+    let mut params = CertificateParams::new(sans)?;
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "heliotorrent (self-signed)");
+
+    let key_pair = KeyPair::generate()?;
+    let cert = params.self_signed(&key_pair)?;
+
+    std::fs::write(cert_path, cert.pem())?;
+    std::fs::write(key_path, key_pair.serialize_pem())?;
+    info!(
+        cert = %cert_path.display(),
+        key = %key_path.display(),
+        "Generated self-signed TLS certificate."
+    );
+    Ok(())
+}
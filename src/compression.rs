@@ -0,0 +1,228 @@
+//! Content negotiation for `Accept-Encoding`.
+//!
+//! Picks the best codec a client supports, preferring a precomputed sidecar
+//! file (`<tile>.br`, `.zst`, `.gz`) over compressing on the fly so the cost
+//! of compressing a tile is paid once, not per request.
+
+use std::io::Write;
+
+use axum::http::{header, HeaderMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Zstd,
+    Brotli,
+    Gzip,
+    Deflate,
+    Identity,
+}
+
+impl Encoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Identity => "identity",
+        }
+    }
+
+    /// File extension of the precomputed sidecar for this encoding, if any.
+    pub fn sidecar_extension(self) -> Option<&'static str> {
+        match self {
+            Encoding::Zstd => Some("zst"),
+            Encoding::Brotli => Some("br"),
+            Encoding::Gzip => Some("gz"),
+            Encoding::Deflate => None,
+            Encoding::Identity => None,
+        }
+    }
+
+}
+
+enum Token {
+    Specific(Encoding),
+    Wildcard,
+}
+
+struct Candidate {
+    token: Token,
+    q: f32,
+}
+
+/// Parses `Accept-Encoding` (RFC 9110 §12.5.3, including q-values) and
+/// returns the best encoding this server supports, or `None` if the client
+/// explicitly refused all of them (e.g. `Accept-Encoding: identity;q=0`).
+pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+    let Some(header_value) = headers.get(header::ACCEPT_ENCODING) else {
+        return Some(Encoding::Identity);
+    };
+    let Ok(header_value) = header_value.to_str() else {
+        return Some(Encoding::Identity);
+    };
+
+    let candidates = parse_accept_encoding(header_value);
+
+    let supported = [
+        Encoding::Zstd,
+        Encoding::Brotli,
+        Encoding::Gzip,
+        Encoding::Deflate,
+        Encoding::Identity,
+    ];
+
+    // `supported` is already ordered by server preference, so on a q-value
+    // tie the first (more preferred) candidate naturally wins.
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in supported {
+        let q = resolve_q(&candidates, encoding);
+        if q <= 0.0 {
+            continue;
+        }
+        if best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+fn parse_accept_encoding(header_value: &str) -> Vec<Candidate> {
+    header_value
+        .split(',')
+        .filter_map(|part| {
+            let part = part.trim();
+            if part.is_empty() {
+                return None;
+            }
+            let mut segments = part.split(';');
+            let name = segments.next()?.trim();
+            let mut q = 1.0f32;
+            for param in segments {
+                let param = param.trim();
+                if let Some(value) = param.strip_prefix("q=") {
+                    q = value.trim().parse().unwrap_or(1.0);
+                }
+            }
+            let token = match name.to_ascii_lowercase().as_str() {
+                "zstd" => Token::Specific(Encoding::Zstd),
+                "br" => Token::Specific(Encoding::Brotli),
+                "gzip" | "x-gzip" => Token::Specific(Encoding::Gzip),
+                "deflate" => Token::Specific(Encoding::Deflate),
+                "identity" => Token::Specific(Encoding::Identity),
+                "*" => Token::Wildcard,
+                _ => return None,
+            };
+            Some(Candidate { token, q })
+        })
+        .collect()
+}
+
+/// Resolves the effective q-value for `encoding`: an explicit entry wins,
+/// otherwise a `*` wildcard applies, otherwise `identity` defaults to 1.0
+/// per RFC 9110 and every other encoding defaults to unacceptable.
+fn resolve_q(candidates: &[Candidate], encoding: Encoding) -> f32 {
+    if let Some(c) = candidates
+        .iter()
+        .find(|c| matches!(&c.token, Token::Specific(e) if *e == encoding))
+    {
+        return c.q;
+    }
+    if let Some(c) = candidates.iter().find(|c| matches!(c.token, Token::Wildcard)) {
+        return c.q;
+    }
+    if encoding == Encoding::Identity {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Compresses `body` on the fly for encodings without a sidecar on disk.
+/// Used as a fallback so the cost is paid once per (path, encoding) and then
+/// served from the LRU like any other cache entry.
+pub fn compress(encoding: Encoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Identity => Ok(body.to_vec()),
+        Encoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Brotli => {
+            let mut out = Vec::new();
+            let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+            writer.write_all(body)?;
+            drop(writer);
+            Ok(out)
+        }
+        Encoding::Zstd => zstd::stream::encode_all(body, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers_with_accept_encoding(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn missing_header_defaults_to_identity() {
+        assert_eq!(negotiate(&HeaderMap::new()), Some(Encoding::Identity));
+    }
+
+    #[test]
+    fn prefers_server_order_on_q_value_tie() {
+        let headers = headers_with_accept_encoding("gzip, br, zstd");
+        assert_eq!(negotiate(&headers), Some(Encoding::Zstd));
+    }
+
+    #[test]
+    fn explicit_q_value_overrides_server_preference() {
+        let headers = headers_with_accept_encoding("zstd;q=0.5, gzip;q=1.0");
+        assert_eq!(negotiate(&headers), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn wildcard_is_used_for_unlisted_encodings() {
+        let headers = headers_with_accept_encoding("*;q=0.5");
+        assert_eq!(negotiate(&headers), Some(Encoding::Zstd));
+    }
+
+    #[test]
+    fn explicit_zero_q_value_rejects_that_encoding() {
+        let headers = headers_with_accept_encoding("identity;q=0, *;q=0");
+        assert_eq!(negotiate(&headers), None);
+    }
+
+    #[test]
+    fn x_gzip_alias_is_recognized() {
+        let headers = headers_with_accept_encoding("x-gzip");
+        assert_eq!(negotiate(&headers), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn encoding_names_are_case_insensitive() {
+        let headers = headers_with_accept_encoding("GZIP");
+        assert_eq!(negotiate(&headers), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn unrecognized_tokens_are_ignored() {
+        let headers = headers_with_accept_encoding("bogus, gzip");
+        assert_eq!(negotiate(&headers), Some(Encoding::Gzip));
+    }
+}
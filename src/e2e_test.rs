@@ -31,6 +31,12 @@ mod tests {
         std::fs::create_dir_all(&test_log_torrent).unwrap();
 
         std::fs::write(test_log_data.join("README.md"), "This is a test README.").unwrap();
+        std::fs::create_dir_all(test_log_data.join("tile/data")).unwrap();
+        std::fs::write(
+            test_log_data.join("tile/data/precompressed.gz"),
+            "pretend-this-is-gzip-bytes",
+        )
+        .unwrap();
         std::fs::write(test_log_torrent.join("feed.xml"), "<xml></xml>").unwrap();
         std::fs::write(
             test_log_torrent.join("L01-0-1048576.torrent"),
@@ -46,6 +52,13 @@ mod tests {
             https_port: None,
             tls_cert: None,
             tls_key: None,
+            tls_hostnames: vec![],
+            disk_cache_max_bytes: None,
+            tracker_udp_port: None,
+            proxy_protocol: false,
+            h3_port: None,
+            metrics_push_url: None,
+            metrics_push_interval_secs: None,
             logs: vec![LogConfig {
                 name: "test_log".to_string(),
                 log_url: "https://tuscolo2025h2.skylight.geomys.org/".to_string(),
@@ -194,6 +207,101 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn test_proxy_range_request_cold_cache() {
+        run_test(|port| async move {
+            // Request a range before the object has ever been fetched, so
+            // the proxy has to forward the Range header upstream rather
+            // than downloading (and caching) the whole object first.
+            let uri = format!("{}/006", TEST_URI);
+            let (range_start, range_end) = (5, 15);
+            let (status, body_range, headers) = get_range(port, &uri, range_start, range_end).await;
+            assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+            assert_eq!(headers.get("X-Cache").unwrap(), "MISS");
+
+            let (_, body_full, _) = get_body(port, &uri, &[]).await;
+            assert_eq!(&body_range[..], &body_full[range_start..=range_end]);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_conditional_request_returns_304() {
+        run_test(|port| async move {
+            let uri = format!("{}/007", TEST_URI);
+
+            let (status, body, headers) = get_body(port, &uri, &[]).await;
+            assert_eq!(status, StatusCode::OK);
+            assert!(!body.is_empty());
+            let etag = headers
+                .get(header::ETAG)
+                .expect("tile response should carry an ETag")
+                .to_str()
+                .unwrap()
+                .to_string();
+            assert!(headers.get(header::LAST_MODIFIED).is_some());
+            assert_eq!(
+                headers.get(header::CACHE_CONTROL).unwrap(),
+                "public, max-age=31536000, immutable"
+            );
+
+            let (status, body, _) = get_body(port, &uri, &[("if-none-match", &etag)]).await;
+            assert_eq!(status, StatusCode::NOT_MODIFIED);
+            assert!(body.is_empty());
+
+            let (status, _, _) =
+                get_body(port, &uri, &[("if-none-match", "\"some-other-etag\"")]).await;
+            assert_eq!(status, StatusCode::OK);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_statistics_page_breaks_down_by_client_prefix() {
+        run_test(|port| async move {
+            let uri = format!("{}/000", TEST_URI);
+            let (status, _, _) = get_body(port, &uri, &[]).await;
+            assert_eq!(status, StatusCode::OK);
+
+            let (status, body, _) = get_body(port, "/statistics", &[]).await;
+            assert_eq!(status, StatusCode::OK);
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            // Tests connect over loopback, so the real client address (read
+            // straight off the TCP socket since proxy_protocol is disabled)
+            // should bucket into the 127.0.0.0/24 prefix.
+            assert!(body.contains("127.0.0.0/24"), "body: {}", body);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_reports_counters() {
+        run_test(|port| async move {
+            let uri = format!("{}/000", TEST_URI);
+            let (status, _, _) = get_body(port, &uri, &[]).await;
+            assert_eq!(status, StatusCode::OK);
+
+            let (status, body, headers) = get_body(port, "/metrics", &[]).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(
+                headers.get(header::CONTENT_TYPE).unwrap(),
+                "text/plain; version=0.0.4; charset=utf-8"
+            );
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(
+                body.contains("heliotorrent_requests_total{log=\"test_log\"} 1"),
+                "body: {}",
+                body
+            );
+            assert!(
+                body.contains("heliotorrent_upstream_fetch_duration_milliseconds_count{log=\"test_log\"} 1"),
+                "body: {}",
+                body
+            );
+        })
+        .await;
+    }
+
     async fn invalid_range_test(port: u16, uri: &str, start: usize, end: usize) {
         let (status, _, _) = get_range(port, uri, start, end).await;
         assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
@@ -273,6 +381,19 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn test_serves_precompressed_sidecar() {
+        run_test(|port| async move {
+            let uri = format!("{}/precompressed", TEST_URI);
+            let (status, body, headers) =
+                get_body(port, &uri, &[("accept-encoding", "gzip")]).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(headers.get(header::CONTENT_ENCODING).unwrap(), "gzip");
+            assert_eq!(&body[..], b"pretend-this-is-gzip-bytes");
+        })
+        .await;
+    }
+
     #[tokio::test]
     async fn test_serve_static_files() {
         run_test(|port| async move {
@@ -285,6 +406,20 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn test_tracker_rejects_unknown_info_hash() {
+        run_test(|port| async move {
+            let uri = format!(
+                "/announce?info_hash={}&peer_id={}&port=6881&left=0",
+                "%01".repeat(20),
+                "%02".repeat(20)
+            );
+            let (status, _, _) = get_body(port, &uri, &[]).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+        })
+        .await;
+    }
+
     #[tokio::test]
     async fn test_readme_special_handling() {
         run_test(|port| async move {
@@ -0,0 +1,111 @@
+//! HTTP/3 (QUIC) listener, served alongside the HTTP/1.1 and TLS listeners.
+//!
+//! Reuses the same rustls certificate chain and the same axum `Router` as
+//! the other listeners, so webseed tiles, the README, torrents and
+//! statistics are all reachable over QUIC too.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::Router;
+use bytes::{Buf, Bytes};
+use h3::server::RequestStream;
+use http::{Request, Response};
+use tower::Service;
+use tracing::{debug, error, warn};
+
+/// Runs the HTTP/3 accept loop forever (until the QUIC endpoint errors).
+pub async fn run_h3_server(
+    addr: SocketAddr,
+    mut tls_config: rustls::ServerConfig,
+    app: Router,
+) -> std::io::Result<()> {
+    tls_config.max_early_data_size = u32::MAX;
+    tls_config.alpn_protocols = vec![b"h3".to_vec()];
+
+    let server_config = quinn::ServerConfig::with_crypto(Arc::new(
+        quinn::crypto::rustls::QuicServerConfig::try_from(tls_config)
+            .map_err(|e| std::io::Error::other(e.to_string()))?,
+    ));
+    let endpoint = quinn::Endpoint::server(server_config, addr)?;
+    tracing::info!("Starting HTTP/3 (QUIC) server on {}.", addr);
+
+    while let Some(incoming) = endpoint.accept().await {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let conn = match incoming.await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("QUIC handshake failed: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = serve_connection(conn, app).await {
+                warn!("HTTP/3 connection ended: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+async fn serve_connection(
+    conn: quinn::Connection,
+    app: Router,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut h3_conn =
+        h3::server::Connection::new(h3_quinn::Connection::new(conn)).await?;
+
+    loop {
+        match h3_conn.accept().await {
+            Ok(Some((req, stream))) => {
+                let mut app = app.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_request(&mut app, req, stream).await {
+                        error!("Failed to serve HTTP/3 request: {}", e);
+                    }
+                });
+            }
+            Ok(None) => break,
+            Err(e) => {
+                debug!("HTTP/3 connection closed: {}", e);
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn handle_request<S>(
+    app: &mut Router,
+    req: Request<()>,
+    mut stream: RequestStream<S, Bytes>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: h3::quic::BidiStream<Bytes> + Send + 'static,
+    S::SendStream: Send,
+    S::RecvStream: Send,
+{
+    // GET-only webseed traffic: Range requests and precondition headers
+    // arrive entirely in the request head, so the body is always empty.
+    let (parts, ()) = req.into_parts();
+    let axum_req = Request::from_parts(parts, axum::body::Body::empty());
+
+    let response = app.call(axum_req).await?;
+    let (parts, body) = response.into_parts();
+
+    let mut h3_response = Response::builder().status(parts.status);
+    *h3_response.headers_mut().unwrap() = parts.headers;
+    stream.send_response(h3_response.body(())?).await?;
+
+    let mut body = std::pin::pin!(body);
+    use http_body_util::BodyExt;
+    while let Some(frame) = body.as_mut().frame().await {
+        let frame = frame?;
+        if let Ok(data) = frame.into_data() {
+            stream.send_data(data.copy_to_bytes(data.remaining())).await?;
+        }
+    }
+    stream.finish().await?;
+    Ok(())
+}
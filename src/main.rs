@@ -2,20 +2,22 @@ use axum::extract::State;
 use axum::{
     Router,
     body::Body,
-    extract::Path,
-    http::{HeaderMap, Response, StatusCode, header},
+    extract::{Extension, Path},
+    http::{HeaderMap, HeaderValue, Response, StatusCode, header},
     response::IntoResponse,
     routing::get,
 };
+use bytes::Bytes;
 use clap::Parser;
-use lru::LruCache;
 use reqwest::Client;
 use serde::Deserialize;
-use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc};
 use std::{
-    net::{IpAddr, Ipv6Addr},
-    num::NonZeroUsize,
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
 };
+use std::net::{IpAddr, Ipv6Addr};
 use tokio::fs;
 use tokio::sync::Mutex;
 use tower_http::services::ServeDir;
@@ -28,22 +30,45 @@ use tokio_rustls::rustls::{self};
 
 #[derive(Default, Clone)]
 pub struct LogStats {
+    pub(crate) bytes_served: u64,
+    pub(crate) request_count: u64,
+    pub(crate) cache_hits: u64,
+    /// Per-client-prefix breakdown, keyed by [`client_prefix_key`], so
+    /// operators can see which peers are pulling the most data without one
+    /// entry per ephemeral port/connection. HTML-only (see `metrics`).
+    per_client: HashMap<String, ClientStats>,
+    /// Upstream fetches that failed or returned a non-success status,
+    /// exported at `/metrics`.
+    pub(crate) upstream_errors: u64,
+    /// Upstream fetch latency, exported at `/metrics` as a histogram.
+    pub(crate) upstream_fetch_latency: metrics::LatencyHistogram,
+}
+
+#[derive(Default, Clone)]
+pub struct ClientStats {
     bytes_served: u64,
     request_count: u64,
-    cache_hits: u64,
 }
 
 type StatsMap = Arc<Mutex<HashMap<String, LogStats>>>;
 
 type ProxyState = (
-    Arc<Mutex<LruCache<String, Vec<u8>>>>, // Cache tracker
-    String,                                // Target host
-    Client,                                // HTTP client
-    PathBuf,                               // Log directory
-    String,                                // Log name
-    StatsMap,                              // Statistics tracker
+    cache::SharedTileCache, // Tile cache
+    String,                 // Target host
+    Client,                 // HTTP client
+    PathBuf,                // Log directory
+    String,                 // Log name
+    StatsMap,               // Statistics tracker
 );
 
+mod cache;
+mod certificates;
+mod compression;
+mod h3;
+mod metrics;
+mod proxy_protocol;
+mod tracker;
+
 #[cfg(test)]
 mod e2e_test;
 
@@ -56,6 +81,41 @@ struct Config {
     http_port: Option<u16>,
     tls_cert: Option<String>,
     tls_key: Option<String>,
+    /// Hostnames/IPs to list as Subject Alternative Names if a TLS
+    /// certificate needs to be generated (see `certificates`). Ignored when
+    /// `tls_cert`/`tls_key` point at files that already exist. Defaults to
+    /// `localhost` when empty.
+    #[serde(default)]
+    tls_hostnames: Vec<String>,
+    /// Maximum total bytes the on-disk tile cache is allowed to use per log
+    /// before the least-recently-used entries are evicted (and their files
+    /// deleted). `None` leaves it unbounded, same as before this existed.
+    #[serde(default)]
+    disk_cache_max_bytes: Option<u64>,
+    /// UDP port for the built-in BEP-15 tracker. The HTTP announce/scrape
+    /// endpoints are always mounted at `/announce` and `/scrape` alongside
+    /// the main router.
+    tracker_udp_port: Option<u16>,
+    /// When true, expect every accepted connection to start with a PROXY
+    /// protocol v1/v2 header (e.g. behind a TCP load balancer) and recover
+    /// the real client address from it instead of the socket peer address.
+    #[serde(default)]
+    proxy_protocol: bool,
+    /// UDP port for an additional HTTP/3 (QUIC) listener serving the same
+    /// router as the HTTP/1.1 and HTTPS listeners. The QUIC handshake uses
+    /// the same certificate as HTTPS: `tls_cert`/`tls_key` if they exist,
+    /// otherwise a generated self-signed one (see `certificates`).
+    h3_port: Option<u16>,
+    /// Pushgateway-compatible URL to periodically POST the `/metrics`
+    /// Prometheus text to, for deployments where the collector can't reach
+    /// back in to scrape it itself. `/metrics` is always mounted regardless
+    /// of whether this is set.
+    #[serde(default)]
+    metrics_push_url: Option<String>,
+    /// How often to push to `metrics_push_url`, in seconds. Ignored unless
+    /// `metrics_push_url` is set. Defaults to 15 seconds.
+    #[serde(default)]
+    metrics_push_interval_secs: Option<u64>,
     logs: Vec<LogConfig>,
 }
 
@@ -154,10 +214,13 @@ async fn launch_proxy(config: Config) -> Result<(), Box<dyn std::error::Error>>
             return Err(err_msg.into());
         }
 
-        let lru_cache = Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(1024).unwrap())));
+        let tile_cache = Arc::new(cache::TileCache::new(
+            cache::default_cache_dir(&log_dir),
+            config.disk_cache_max_bytes,
+        ));
 
         log_caches.push((
-            lru_cache,
+            tile_cache,
             log.log_url.clone(),
             client.clone(),
             log_dir,
@@ -166,47 +229,92 @@ async fn launch_proxy(config: Config) -> Result<(), Box<dyn std::error::Error>>
         ));
     }
 
-    // Create the router
-    let app = create_multi_router(log_caches, config.torrent_dir);
+    let tracker_state = Arc::new(tracker::TrackerState::new(torrent_dir.clone()));
+    tokio::spawn(tracker_state.clone().run_maintenance());
+
+    if let Some(tracker_udp_port) = config.tracker_udp_port {
+        let tracker_addr = SocketAddr::new(IpAddr::from(Ipv6Addr::UNSPECIFIED), tracker_udp_port);
+        let tracker_state = tracker_state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = tracker::run_udp_tracker(tracker_addr, tracker_state).await {
+                error!("UDP tracker stopped: {}", e);
+            }
+        });
+    }
+
+    // Create the router. When HTTP/3 is enabled, every HTTP/1.1 and HTTPS
+    // response advertises it via `Alt-Svc` so clients know they can upgrade.
+    let (mut app, stats) = create_multi_router(log_caches, config.torrent_dir, tracker_state);
+    if let Some(h3_port) = config.h3_port {
+        app = app.layer(tower_http::set_header::SetResponseHeaderLayer::overriding(
+            header::HeaderName::from_static("alt-svc"),
+            HeaderValue::from_str(&format!("h3=\":{h3_port}\"")).unwrap(),
+        ));
+    }
+
+    if let Some(push_url) = config.metrics_push_url {
+        let interval =
+            std::time::Duration::from_secs(config.metrics_push_interval_secs.unwrap_or(15));
+        let client = client.clone();
+        tokio::spawn(metrics::run_push_exporter(stats, client, push_url, interval));
+    }
 
     let mut handles = vec![];
 
     if let Some(http_port) = config.http_port {
         let http_addr = SocketAddr::new(IpAddr::from(Ipv6Addr::UNSPECIFIED), http_port);
         info!("Starting HTTP server on {}.", http_addr);
-        let http_server = axum_server::bind(http_addr).serve(app.clone().into_make_service());
+        let http_server = axum_server::bind(http_addr)
+            .acceptor(proxy_protocol::ProxyProtocolAcceptor::new(
+                config.proxy_protocol,
+            ))
+            .serve(app.clone().into_make_service_with_connect_info::<SocketAddr>());
         handles.push(tokio::spawn(http_server));
     }
 
-    if let Some(https_port) = config.https_port {
-        if let (Some(cert_path), Some(key_path)) = (config.tls_cert, config.tls_key) {
+    if config.https_port.is_some() || config.h3_port.is_some() {
+        rustls::crypto::aws_lc_rs::default_provider()
+            .install_default()
+            .unwrap();
+
+        // Prefer an operator-supplied certificate, but fall back to a
+        // self-signed one (generated once and reused across restarts) so
+        // first-run deployments and local testing work without manual
+        // openssl steps.
+        let (cert_path, key_path) = certificates::ensure_certificate(
+            &data_dir,
+            config.tls_cert.as_deref(),
+            config.tls_key.as_deref(),
+            &config.tls_hostnames,
+        )?;
+
+        if let Some(https_port) = config.https_port {
             let https_addr = SocketAddr::new(IpAddr::from(Ipv6Addr::UNSPECIFIED), https_port);
             info!("HTTPS enabled. Starting HTTPS server on {}.", https_addr);
-            rustls::crypto::aws_lc_rs::default_provider()
-                .install_default()
-                .unwrap();
-            let certs = {
-                let cert_file = &mut BufReader::new(std::fs::File::open(cert_path)?);
-                certs(cert_file).collect::<Result<Vec<_>, _>>()?
-            };
-            let key = {
-                let key_file = &mut BufReader::new(std::fs::File::open(key_path)?);
-                private_key(key_file)?.ok_or("No private key found in key file")?
-            };
-
-            let tls_config = rustls::ServerConfig::builder()
-                .with_no_client_auth()
-                .with_single_cert(certs, key)?;
-
+            let mut tls_config = load_rustls_server_config(&cert_path, &key_path)?;
+            // Without this, clients never see h2 in the TLS ALPN offer and
+            // fall back to one HTTP/1.1 connection per request, which is
+            // wasteful for range-heavy webseed clients pulling many tiles
+            // off the same log. `ServerConfig::builder()` already offers
+            // TLS 1.3 (and 1.2) by default, so nothing else to confirm there.
+            tls_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
             let tls_config = RustlsConfig::from_config(Arc::new(tls_config));
+            let acceptor = axum_server::tls_rustls::RustlsAcceptor::new(tls_config)
+                .acceptor(proxy_protocol::ProxyProtocolAcceptor::new(config.proxy_protocol));
 
-            let https_server = axum_server::bind_rustls(https_addr, tls_config)
-                .serve(app.clone().into_make_service());
+            let https_server = axum_server::bind(https_addr)
+                .acceptor(acceptor)
+                .serve(app.clone().into_make_service_with_connect_info::<SocketAddr>());
             handles.push(tokio::spawn(https_server));
-        } else {
-            warn!(
-                "https_port is set, but tls_cert or tls_key is missing. HTTPS server will not be started."
-            );
+        }
+
+        if let Some(h3_port) = config.h3_port {
+            let h3_addr = SocketAddr::new(IpAddr::from(Ipv6Addr::UNSPECIFIED), h3_port);
+            let h3_tls_config = load_rustls_server_config(&cert_path, &key_path)?;
+            let app = app.clone();
+            handles.push(tokio::spawn(async move {
+                h3::run_h3_server(h3_addr, h3_tls_config, app).await
+            }));
         }
     }
 
@@ -225,7 +333,33 @@ async fn launch_proxy(config: Config) -> Result<(), Box<dyn std::error::Error>>
     first_result?.map_err(|e| e.into())
 }
 
-pub fn create_multi_router(log_caches: Vec<ProxyState>, static_dir: String) -> Router {
+/// Loads a certificate chain and private key from disk and builds a rustls
+/// server config from them. Called once per TLS-based listener (HTTPS,
+/// HTTP/3) since each needs its own `rustls::ServerConfig` (ALPN protocols
+/// differ), even though they share the same certificate material.
+fn load_rustls_server_config(
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> Result<rustls::ServerConfig, Box<dyn std::error::Error>> {
+    let cert_chain = {
+        let cert_file = &mut BufReader::new(std::fs::File::open(cert_path)?);
+        certs(cert_file).collect::<Result<Vec<_>, _>>()?
+    };
+    let key = {
+        let key_file = &mut BufReader::new(std::fs::File::open(key_path)?);
+        private_key(key_file)?.ok_or("No private key found in key file")?
+    };
+
+    Ok(rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)?)
+}
+
+pub fn create_multi_router(
+    log_caches: Vec<ProxyState>,
+    static_dir: String,
+    tracker_state: Arc<tracker::TrackerState>,
+) -> (Router, StatsMap) {
     let mut app = Router::new();
     let stats = Arc::new(Mutex::new(HashMap::new()));
 
@@ -247,7 +381,13 @@ pub fn create_multi_router(log_caches: Vec<ProxyState>, static_dir: String) -> R
     app = app.nest("/webseed", webseed_router);
 
     // Add statistics endpoint
-    app = app.route("/statistics", get(statistics_handler).with_state(stats));
+    app = app.route(
+        "/statistics",
+        get(statistics_handler).with_state(stats.clone()),
+    );
+
+    // Add Prometheus metrics endpoint
+    app = app.route("/metrics", get(metrics_handler).with_state(stats.clone()));
 
     // Add static file serving if directory is provided
     info!(
@@ -256,7 +396,27 @@ pub fn create_multi_router(log_caches: Vec<ProxyState>, static_dir: String) -> R
     );
     app = app.nest_service("/torrents", ServeDir::new(static_dir));
 
-    app
+    // Mount the BEP-3/BEP-48 HTTP announce/scrape endpoints so clients that
+    // don't speak the UDP tracker protocol can still join the swarm.
+    app = app.merge(tracker::http_router(tracker_state));
+
+    (app, stats)
+}
+
+/// Serves the same counters as `/statistics`, plus upstream fetch latency
+/// and error counts, in Prometheus text exposition format.
+async fn metrics_handler(State(stats): axum::extract::State<StatsMap>) -> impl IntoResponse {
+    let stats_guard = stats.lock().await;
+    let body = metrics::render(&stats_guard);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )
+        .body(Body::from(body))
+        .unwrap()
 }
 
 fn format_number(num: u64) -> String {
@@ -316,6 +476,22 @@ async fn statistics_handler(State(stats): axum::extract::State<StatsMap>) -> imp
             }
         ));
         stats_html.push_str("</ul>\n");
+
+        if !stats.per_client.is_empty() {
+            let mut clients: Vec<(&String, &ClientStats)> = stats.per_client.iter().collect();
+            clients.sort_by(|a, b| b.1.bytes_served.cmp(&a.1.bytes_served));
+
+            stats_html.push_str("<h3>Top client prefixes</h3>\n<ul>\n");
+            for (prefix, client_stats) in clients.into_iter().take(20) {
+                stats_html.push_str(&format!(
+                    "<li>{}: {} served over {} requests</li>\n",
+                    prefix,
+                    format_bytes(client_stats.bytes_served),
+                    format_number(client_stats.request_count)
+                ));
+            }
+            stats_html.push_str("</ul>\n");
+        }
     }
 
     let html = format!(
@@ -349,6 +525,69 @@ async fn statistics_handler(State(stats): axum::extract::State<StatsMap>) -> imp
 
 #[instrument(skip(headers, state))]
 async fn proxy_handler(
+    path: Path<String>,
+    headers: HeaderMap,
+    // The PROXY protocol acceptor only wraps the plain TCP/TLS listeners, so
+    // HTTP/3 (QUIC) requests never carry this extension; they are counted
+    // under the unspecified address (`::/48`) rather than rejected outright.
+    client_addr: Option<Extension<proxy_protocol::RealClientAddr>>,
+    state: axum::extract::State<ProxyState>,
+) -> axum::response::Response {
+    let name = state.4.clone();
+    let stats = state.5.clone();
+    let response = proxy_handler_inner(path, headers, state).await;
+    record_client_stats(&stats, &name, client_addr, &response).await;
+    response
+}
+
+/// Buckets a client address into a `/24` (IPv4) or `/48` (IPv6) prefix so
+/// per-client statistics show which peers are pulling the most data without
+/// growing one entry per ephemeral address.
+fn client_prefix_key(addr: IpAddr) -> String {
+    // IPv4 clients of the dual-stack `[::]` listeners arrive as
+    // IPv4-mapped IPv6 addresses; bucket them as the IPv4 they are.
+    match addr.to_canonical() {
+        IpAddr::V4(v4) => {
+            let octets = v4.octets();
+            format!("{}.{}.{}.0/24", octets[0], octets[1], octets[2])
+        }
+        IpAddr::V6(v6) => {
+            let mut octets = v6.octets();
+            for byte in &mut octets[6..] {
+                *byte = 0;
+            }
+            format!("{}/48", Ipv6Addr::from(octets))
+        }
+    }
+}
+
+/// Attributes the response just served to the client's address prefix,
+/// reading `Content-Length` rather than threading a byte count through every
+/// response-building function.
+async fn record_client_stats(
+    stats: &StatsMap,
+    name: &str,
+    client_addr: Option<Extension<proxy_protocol::RealClientAddr>>,
+    response: &axum::response::Response,
+) {
+    let addr = client_addr.map_or(IpAddr::from(Ipv6Addr::UNSPECIFIED), |Extension(addr)| {
+        addr.0.ip()
+    });
+    let bytes = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    let mut stats_guard = stats.lock().await;
+    let log_stats = stats_guard.entry(name.to_string()).or_default();
+    let client_stats = log_stats.per_client.entry(client_prefix_key(addr)).or_default();
+    client_stats.request_count += 1;
+    client_stats.bytes_served += bytes;
+}
+
+async fn proxy_handler_inner(
     Path(path): Path<String>,
     headers: HeaderMap,
     state: axum::extract::State<ProxyState>,
@@ -373,46 +612,480 @@ async fn proxy_handler(
         return handle_readme(log_data_dir, &headers, name, stats).await;
     }
 
-    let cache_key = path.clone();
-    let mut is_cache_hit = false;
+    let Some(encoding) = compression::negotiate(&headers) else {
+        return StatusCode::NOT_ACCEPTABLE.into_response();
+    };
 
-    let mut body = get_cached_body(cache, &cache_key).await;
-    if body.is_some() {
-        is_cache_hit = true;
-        // Update cache hit count
-        let mut stats_guard = stats.lock().await;
-        let log_stats = stats_guard.entry(name.clone()).or_default();
-        log_stats.cache_hits += 1;
+    // A precomputed sidecar (e.g. `data/000.zst`) next to the tile avoids
+    // paying the compression cost on every request.
+    if let Some(ext) = encoding.sidecar_extension() {
+        let sidecar_path = log_data_dir.join(format!("{path}.{ext}"));
+        if let Ok(body) = fs::read(&sidecar_path).await {
+            debug!(path = %sidecar_path.display(), "Serving precompressed sidecar");
+            let modified = fs::metadata(&sidecar_path)
+                .await
+                .and_then(|meta| meta.modified())
+                .unwrap_or_else(|_| std::time::SystemTime::now());
+            let tile = cache::CachedTile::Hot {
+                etag: cache::etag_for_body(&body),
+                bytes: Bytes::from(body),
+                modified,
+            };
+            return serve_tile(tile, encoding, true, &headers, name, stats).await;
+        }
+    }
+
+    let cache_key = if encoding == compression::Encoding::Identity {
+        path.clone()
     } else {
-        body = fetch_and_cache_body(client, cache, log_url, &path, &cache_key).await;
+        format!("{path}#{}", encoding.as_str())
+    };
+
+    let existing_tile = cache.get(&cache_key).await;
+
+    // The object isn't fully cached yet: if the client only wants a byte
+    // range of it, forward an equivalent Range request upstream instead of
+    // downloading the whole thing. Compressed variants always need the
+    // whole body to produce (compression can't run on a partial stream),
+    // so this only applies to the identity encoding.
+    if existing_tile.is_none()
+        && encoding == compression::Encoding::Identity
+        && let Some(range_header) = headers.get(header::RANGE)
+        && let Ok(range_str) = range_header.to_str()
+        && let Some(resp) =
+            serve_range_miss(cache, client, log_url, &path, &cache_key, range_str, name, stats)
+                .await
+    {
+        return with_encoding_headers(resp, encoding);
+    }
+
+    let (tile, is_cache_hit) = match existing_tile {
+        Some(tile) => {
+            let mut stats_guard = stats.lock().await;
+            let log_stats = stats_guard.entry(name.clone()).or_default();
+            log_stats.cache_hits += 1;
+            (tile, true)
+        }
+        None => {
+            let raw = get_or_fetch_raw(cache, client, log_url, &path, name, stats).await;
+            let Some(raw) = raw else {
+                return StatusCode::BAD_GATEWAY.into_response();
+            };
+            let Ok(encoded) = compression::compress(encoding, &raw) else {
+                return StatusCode::BAD_GATEWAY.into_response();
+            };
+            if encoding != compression::Encoding::Identity
+                && let Err(e) = cache.put(&cache_key, &encoded, None).await
+            {
+                error!("Failed to write tile to disk cache: {}", e);
+            }
+            // Re-resolve through the cache so we serve the bounded-memory
+            // representation (streamed from disk for large tiles) rather
+            // than holding the freshly fetched/compressed body in memory;
+            // if that failed (e.g. read-only filesystem) fall back to
+            // serving directly from the bytes we already have.
+            let tile = cache.get(&cache_key).await.unwrap_or_else(|| cache::CachedTile::Hot {
+                etag: cache::etag_for_body(&encoded),
+                bytes: Bytes::from(encoded),
+                modified: std::time::SystemTime::now(),
+            });
+            (tile, false)
+        }
+    };
+
+    serve_tile(tile, encoding, is_cache_hit, &headers, name, stats).await
+}
+
+/// Fetches a raw (uncompressed) upstream body, checking the tile cache
+/// first under the plain (encoding-independent) `path` key.
+async fn get_or_fetch_raw(
+    cache: &cache::SharedTileCache,
+    client: &Client,
+    target_host: &str,
+    path: &str,
+    name: &str,
+    stats: &StatsMap,
+) -> Option<Vec<u8>> {
+    match cache.get(path).await {
+        Some(cache::CachedTile::Hot { bytes, .. }) => return Some(bytes.to_vec()),
+        Some(cache::CachedTile::Disk { path, .. }) => {
+            if let Ok(bytes) = fs::read(&path).await {
+                return Some(bytes);
+            }
+        }
+        None => {}
+    }
+
+    // The disk-index entry may have been LRU-evicted while the underlying
+    // file (at a path hashed from the cache key; only disk-budget eviction
+    // deletes it, the index's entry-count cap does not) is still there; revalidate
+    // with upstream instead of blindly re-downloading an immutable tile.
+    let orphaned = cache.read_orphaned(path).await;
+    fetch_and_cache_raw(client, cache, target_host, path, orphaned, name, stats).await
+}
+
+/// Outcome of forwarding a `Range` request to the upstream.
+enum RangeFetch {
+    /// Upstream honored the range and returned `206 Partial Content`.
+    Partial {
+        bytes: Vec<u8>,
+        offset: u64,
+        total_len: u64,
+    },
+    /// Upstream ignored `Range` and returned the whole object.
+    Full { bytes: Vec<u8> },
+    /// Upstream rejected the range with `416 Range Not Satisfiable`.
+    NotSatisfiable,
+}
+
+/// Forwards a `Range: bytes=start-end` (or open-ended `bytes=start-`)
+/// request to the upstream so only the requested span is downloaded.
+async fn fetch_range_from_upstream(
+    client: &Client,
+    target_host: &str,
+    path: &str,
+    start: u64,
+    end: Option<u64>,
+) -> Option<RangeFetch> {
+    let target_url = {
+        let base = target_host.trim_end_matches('/');
+        let path = path.trim_start_matches('/');
+        format!("{base}/{path}")
+    };
+    let range_value = match end {
+        Some(end) => format!("bytes={start}-{end}"),
+        None => format!("bytes={start}-"),
+    };
+    debug!(target_url = %target_url, range = %range_value, "Fetching byte range from upstream");
+
+    let resp = match client
+        .get(&target_url)
+        .header(header::RANGE, range_value)
+        .send()
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Range request to upstream failed: {}", e);
+            return None;
+        }
+    };
+
+    match resp.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT => {
+            let Some(total_len) = resp
+                .headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_content_range_total)
+            else {
+                error!("Upstream returned 206 without a parseable Content-Range");
+                return None;
+            };
+            match resp.bytes().await {
+                Ok(bytes) => Some(RangeFetch::Partial {
+                    bytes: bytes.to_vec(),
+                    offset: start,
+                    total_len,
+                }),
+                Err(e) => {
+                    error!("Failed to read upstream range body: {}", e);
+                    None
+                }
+            }
+        }
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE => Some(RangeFetch::NotSatisfiable),
+        status if status.is_success() => match resp.bytes().await {
+            Ok(bytes) => Some(RangeFetch::Full {
+                bytes: bytes.to_vec(),
+            }),
+            Err(e) => {
+                error!("Failed to read upstream body: {}", e);
+                None
+            }
+        },
+        status => {
+            error!("Upstream responded with error status: {}", status);
+            None
+        }
     }
+}
+
+/// Parses the total length out of a `Content-Range: bytes start-end/total` header.
+fn parse_content_range_total(value: &str) -> Option<u64> {
+    value.rsplit('/').next()?.parse().ok()
+}
 
-    let body = match body {
-        Some(b) => b,
-        None => return StatusCode::BAD_GATEWAY.into_response(),
+/// Parses a `Range: bytes=start-end` or `bytes=start-` header without
+/// requiring the object's total length up front, so it can be forwarded to
+/// the upstream before we know it.
+fn parse_range_bounds(range: &str) -> Option<(u64, Option<u64>)> {
+    let range = range.strip_prefix("bytes=")?;
+    let (start, end) = range.split_once('-')?;
+    let start = start.parse::<u64>().ok()?;
+    let end = if end.is_empty() {
+        None
+    } else {
+        Some(end.parse::<u64>().ok()?)
     };
+    Some((start, end))
+}
+
+/// Handles a `Range` request for an object that isn't fully cached yet, by
+/// forwarding the range upstream rather than downloading the whole object.
+/// Returns `None` for a malformed `Range` header, leaving the caller to fall
+/// back to the existing full-object fetch-and-cache path.
+async fn serve_range_miss(
+    cache: &cache::SharedTileCache,
+    client: &Client,
+    target_host: &str,
+    path: &str,
+    cache_key: &str,
+    range_str: &str,
+    name: &str,
+    stats: &StatsMap,
+) -> Option<axum::response::Response> {
+    let (start, requested_end) = parse_range_bounds(range_str)?;
+
+    // A previous range read may already have fetched this exact span.
+    if let Some(end) = requested_end
+        && let Some((bytes, total_len)) = cache.get_sparse_range(cache_key, start, end + 1).await
+    {
+        return Some(range_hit_response(bytes, start, end, total_len, true, name, stats).await);
+    }
+
+    let started = std::time::Instant::now();
+    let fetch_result = fetch_range_from_upstream(client, target_host, path, start, requested_end).await;
+    metrics::record_upstream_fetch(stats, name, started.elapsed(), fetch_result.is_some()).await;
+
+    match fetch_result {
+        Some(RangeFetch::Partial {
+            bytes,
+            offset,
+            total_len,
+        }) => {
+            let end_inclusive = offset + bytes.len() as u64 - 1;
+            if let Some(complete) = cache
+                .insert_sparse_range(cache_key, offset, bytes.clone(), total_len)
+                .await
+                && let Err(e) = cache.put(cache_key, &complete, None).await
+            {
+                error!(
+                    "Failed to write completed range-fetched object to disk cache: {}",
+                    e
+                );
+            }
+            Some(
+                range_hit_response(bytes, offset, end_inclusive, total_len, false, name, stats)
+                    .await,
+            )
+        }
+        Some(RangeFetch::NotSatisfiable) => Some(
+            Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .body(Body::empty())
+                .unwrap(),
+        ),
+        Some(RangeFetch::Full { bytes }) => {
+            // Upstream ignored our Range header; cache the whole object and
+            // slice it locally like any other fully-cached tile.
+            if let Err(e) = cache.put(cache_key, &bytes, None).await {
+                error!("Failed to write fetched tile to disk cache: {}", e);
+            }
+            let tile = cache::CachedTile::Hot {
+                etag: cache::etag_for_body(&bytes),
+                bytes: Bytes::from(bytes),
+                modified: std::time::SystemTime::now(),
+            };
+            serve_tile_range(&tile, range_str, false, name, stats).await
+        }
+        None => Some(StatusCode::BAD_GATEWAY.into_response()),
+    }
+}
+
+async fn range_hit_response(
+    bytes: Vec<u8>,
+    start: u64,
+    end_inclusive: u64,
+    total_len: u64,
+    is_cache_hit: bool,
+    name: &str,
+    stats: &StatsMap,
+) -> axum::response::Response {
+    {
+        let mut stats_guard = stats.lock().await;
+        let log_stats = stats_guard.entry(name.to_string()).or_default();
+        log_stats.bytes_served += bytes.len() as u64;
+    }
+    let mut response = Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {start}-{end_inclusive}/{total_len}"),
+        )
+        .header(header::CONTENT_LENGTH, bytes.len());
+    response = response.header("X-Cache", if is_cache_hit { "HIT" } else { "MISS" });
+    response.body(Body::from(bytes)).unwrap()
+}
+
+/// CT tiles are immutable once published, so once a client has validated a
+/// copy it can be cached forever.
+const TILE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Attaches `ETag`, `Last-Modified` and `Cache-Control` validators for
+/// `tile` to `response`, so both full and range responses for the same
+/// tile validate identically.
+fn with_cache_validators(
+    mut response: Response<Body>,
+    tile: &cache::CachedTile,
+) -> Response<Body> {
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(tile.etag()) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(tile.modified())) {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(TILE_CACHE_CONTROL),
+    );
+    response
+}
+
+/// True if the client's `If-None-Match` or `If-Modified-Since` header shows
+/// it already holds this exact tile, so we can answer `304` instead of
+/// resending the body.
+fn is_not_modified(headers: &HeaderMap, tile: &cache::CachedTile) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        return if_none_match
+            .to_str()
+            .map(|value| {
+                value
+                    .split(',')
+                    .any(|tag| tag.trim() == "*" || tag.trim() == tile.etag())
+            })
+            .unwrap_or(false);
+    }
+    if let Some(if_modified_since) = headers.get(header::IF_MODIFIED_SINCE)
+        && let Ok(if_modified_since) = if_modified_since.to_str()
+        && let Ok(since) = httpdate::parse_http_date(if_modified_since)
+    {
+        // HTTP dates have one-second resolution, so compare whole seconds;
+        // otherwise our own `Last-Modified` echoed back would never match.
+        let secs = |t: std::time::SystemTime| {
+            t.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs())
+        };
+        return secs(tile.modified()) <= secs(since);
+    }
+    false
+}
+
+/// Serves a cached tile, applying Range handling and the cache/encoding
+/// response headers. The tile body is streamed from disk in fixed-size
+/// chunks unless it's small enough to be held in the in-memory hot set.
+async fn serve_tile(
+    tile: cache::CachedTile,
+    encoding: compression::Encoding,
+    is_cache_hit: bool,
+    headers: &HeaderMap,
+    name: &str,
+    stats: &StatsMap,
+) -> axum::response::Response {
+    if is_not_modified(headers, &tile) {
+        debug!("Conditional request validators matched; returning 304");
+        let response = with_cache_validators(
+            Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap(),
+            &tile,
+        );
+        return with_encoding_headers(response, encoding);
+    }
 
-    // Check for range request
     if let Some(range_header) = headers.get(header::RANGE)
         && let Ok(range_str) = range_header.to_str()
-        && let Some(resp) = handle_range_response(&body, range_str, is_cache_hit, name, stats).await
+        && let Some(resp) = serve_tile_range(&tile, range_str, is_cache_hit, name, stats).await
     {
-        return resp;
+        let resp = with_cache_validators(resp, &tile);
+        return with_encoding_headers(resp, encoding);
     }
 
-    // Update bytes served
+    let len = tile.len();
     {
         let mut stats_guard = stats.lock().await;
-        let log_stats = stats_guard.entry(name.clone()).or_default();
-        log_stats.bytes_served += body.len() as u64;
+        let log_stats = stats_guard.entry(name.to_string()).or_default();
+        log_stats.bytes_served += len;
     }
 
-    //TODO: Why are the emitted Client Hello's only TLS1.2 and not using ALPN or HTTP2?
+    debug!(len, "Serving full response");
+    let body = match tile.body_for_range(0, len).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to stream cached tile: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
 
-    debug!("Serving full response");
     let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_LENGTH, body.len());
+        .header(header::CONTENT_LENGTH, len);
+
+    if is_cache_hit {
+        response = response.header("X-Cache", "HIT");
+    } else {
+        response = response.header("X-Cache", "MISS");
+    }
+
+    let response = with_cache_validators(response.body(body).unwrap(), &tile);
+    with_encoding_headers(response, encoding)
+}
+
+async fn serve_tile_range(
+    tile: &cache::CachedTile,
+    range_str: &str,
+    is_cache_hit: bool,
+    log_name: &str,
+    stats: &StatsMap,
+) -> Option<Response<Body>> {
+    let total = tile.len();
+    let (start, end) = parse_range_header(range_str, total as usize)?;
+    let (start, end) = (start as u64, end as u64);
+
+    debug!(start, end = end - 1, total, "Serving byte range");
+    if start >= total || end > total || start >= end {
+        warn!(
+            "Invalid range request: start={}, end={}, body_length={}",
+            start, end, total
+        );
+        return Some(
+            Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .body(Body::empty())
+                .unwrap(),
+        );
+    }
+
+    let body = match tile.body_for_range(start, end).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to stream cached tile range: {}", e);
+            return Some(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    // Update bytes served for this range
+    {
+        let mut stats_guard = stats.lock().await;
+        let log_stats = stats_guard.entry(log_name.to_string()).or_default();
+        log_stats.bytes_served += end - start;
+    }
+
+    let mut response = Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end - 1, total))
+        .header(header::CONTENT_LENGTH, end - start);
 
     if is_cache_hit {
         response = response.header("X-Cache", "HIT");
@@ -420,7 +1093,21 @@ async fn proxy_handler(
         response = response.header("X-Cache", "MISS");
     }
 
-    response.body(Body::from(body)).unwrap()
+    Some(response.body(body).unwrap())
+}
+
+fn with_encoding_headers(
+    mut response: Response<Body>,
+    encoding: compression::Encoding,
+) -> Response<Body> {
+    let headers = response.headers_mut();
+    if encoding != compression::Encoding::Identity
+        && let Ok(value) = HeaderValue::from_str(encoding.as_str())
+    {
+        headers.insert(header::CONTENT_ENCODING, value);
+    }
+    headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    response
 }
 
 async fn handle_readme(
@@ -496,52 +1183,75 @@ fn sanitize_path(path: &str) -> String {
     result
 }
 
-async fn get_cached_body(
-    cache: &Arc<Mutex<LruCache<String, Vec<u8>>>>,
-    cache_key: &str,
-) -> Option<Vec<u8>> {
-    let mut cache_guard = cache.lock().await;
-    cache_guard.get(cache_key).cloned()
-}
-
-async fn fetch_and_cache_body(
+async fn fetch_and_cache_raw(
     client: &Client,
-    cache: &Arc<Mutex<LruCache<String, Vec<u8>>>>,
+    cache: &cache::SharedTileCache,
     target_host: &str,
-    final_path: &str,
-    cache_key: &str,
+    path: &str,
+    orphaned: Option<(Vec<u8>, String)>,
+    name: &str,
+    stats: &StatsMap,
 ) -> Option<Vec<u8>> {
     let target_url = {
         let base = target_host.trim_end_matches('/');
-        let path = final_path.trim_start_matches('/');
+        let path = path.trim_start_matches('/');
         format!("{base}/{path}")
     };
     debug!(target_url = %target_url, "Fetching full file from upstream");
 
+    let mut request = client.get(&target_url);
+    if let Some((_, etag)) = &orphaned {
+        request = request.header(header::IF_NONE_MATCH, etag.as_str());
+    }
+
+    let started = std::time::Instant::now();
+
     //TODO Not sure if this is reusing connections properly.
-    let resp = match client.get(&target_url).send().await {
+    let resp = match request.send().await {
         Ok(r) => r,
         Err(e) => {
             error!("Request to upstream failed: {}", e);
+            metrics::record_upstream_fetch(stats, name, started.elapsed(), false).await;
             return None;
         }
     };
 
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED
+        && let Some((bytes, etag)) = orphaned
+    {
+        debug!("Upstream confirmed orphaned tile is unchanged; re-indexing without re-downloading");
+        metrics::record_upstream_fetch(stats, name, started.elapsed(), true).await;
+        if let Err(e) = cache.put(path, &bytes, Some(&etag)).await {
+            error!("Failed to write tile to disk cache: {}", e);
+        }
+        return Some(bytes);
+    }
+
     if !resp.status().is_success() {
         error!("Upstream responded with error status: {}", resp.status());
+        metrics::record_upstream_fetch(stats, name, started.elapsed(), false).await;
         return None;
     }
 
+    let upstream_etag = resp
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
     let body = match resp.bytes().await {
         Ok(b) => b.to_vec(),
         Err(e) => {
             error!("Failed to read response body: {}", e);
+            metrics::record_upstream_fetch(stats, name, started.elapsed(), false).await;
             return None;
         }
     };
+    metrics::record_upstream_fetch(stats, name, started.elapsed(), true).await;
 
-    let mut cache_guard = cache.lock().await;
-    cache_guard.put(cache_key.to_string(), body.clone());
+    if let Err(e) = cache.put(path, &body, upstream_etag.as_deref()).await {
+        error!("Failed to write fetched tile to disk cache: {}", e);
+    }
     debug!("Cached full response");
     Some(body)
 }
@@ -0,0 +1,157 @@
+//! Prometheus metrics export.
+//!
+//! The `/statistics` page renders an HTML summary of the in-process
+//! [`StatsMap`](crate::StatsMap) for humans; this module renders the same
+//! counters (plus upstream fetch latency and error counts, which aren't
+//! shown on the HTML page) in Prometheus text exposition format at
+//! `/metrics`, so operators can alert on cache-hit-rate drops or upstream
+//! failures instead of eyeballing HTML. Per-client-prefix stats stay
+//! HTML-only: exporting them as labels would give Prometheus one time
+//! series per observed `/24`/`/48`, which is the kind of unbounded
+//! cardinality it's bad at.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::{LogStats, StatsMap};
+
+/// Upper bounds (inclusive, milliseconds) of the upstream fetch latency
+/// histogram buckets, following Prometheus's cumulative `le` convention.
+const LATENCY_BUCKETS_MS: [u64; 9] = [10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+#[derive(Default, Clone)]
+pub struct LatencyHistogram {
+    /// `bucket_counts[i]` is the number of observations `<= LATENCY_BUCKETS_MS[i]`.
+    bucket_counts: [u64; LATENCY_BUCKETS_MS.len()],
+    count: u64,
+    sum_ms: u64,
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        self.count += 1;
+        self.sum_ms += ms;
+        for (bucket, bound) in self.bucket_counts.iter_mut().zip(LATENCY_BUCKETS_MS) {
+            if ms <= bound {
+                *bucket += 1;
+            }
+        }
+    }
+}
+
+/// Renders `stats` (keyed by log name) as Prometheus text exposition format.
+pub fn render(stats: &HashMap<String, LogStats>) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP heliotorrent_bytes_served_total Bytes served to clients.\n");
+    out.push_str("# TYPE heliotorrent_bytes_served_total counter\n");
+    for (name, s) in stats {
+        out.push_str(&format!(
+            "heliotorrent_bytes_served_total{{log=\"{name}\"}} {}\n",
+            s.bytes_served
+        ));
+    }
+
+    out.push_str("# HELP heliotorrent_requests_total Requests handled.\n");
+    out.push_str("# TYPE heliotorrent_requests_total counter\n");
+    for (name, s) in stats {
+        out.push_str(&format!(
+            "heliotorrent_requests_total{{log=\"{name}\"}} {}\n",
+            s.request_count
+        ));
+    }
+
+    out.push_str("# HELP heliotorrent_cache_hits_total Cache hits.\n");
+    out.push_str("# TYPE heliotorrent_cache_hits_total counter\n");
+    for (name, s) in stats {
+        out.push_str(&format!(
+            "heliotorrent_cache_hits_total{{log=\"{name}\"}} {}\n",
+            s.cache_hits
+        ));
+    }
+
+    out.push_str("# HELP heliotorrent_cache_hit_ratio Cache hit rate in [0, 1], derived from heliotorrent_cache_hits_total / heliotorrent_requests_total.\n");
+    out.push_str("# TYPE heliotorrent_cache_hit_ratio gauge\n");
+    for (name, s) in stats {
+        let ratio = if s.request_count > 0 {
+            s.cache_hits as f64 / s.request_count as f64
+        } else {
+            0.0
+        };
+        out.push_str(&format!(
+            "heliotorrent_cache_hit_ratio{{log=\"{name}\"}} {ratio}\n"
+        ));
+    }
+
+    out.push_str("# HELP heliotorrent_upstream_errors_total Upstream fetches that failed or returned a non-success status.\n");
+    out.push_str("# TYPE heliotorrent_upstream_errors_total counter\n");
+    for (name, s) in stats {
+        out.push_str(&format!(
+            "heliotorrent_upstream_errors_total{{log=\"{name}\"}} {}\n",
+            s.upstream_errors
+        ));
+    }
+
+    out.push_str(
+        "# HELP heliotorrent_upstream_fetch_duration_milliseconds Time spent fetching a tile or range from the upstream log.\n",
+    );
+    out.push_str("# TYPE heliotorrent_upstream_fetch_duration_milliseconds histogram\n");
+    for (name, s) in stats {
+        let hist = &s.upstream_fetch_latency;
+        for (bound, count) in LATENCY_BUCKETS_MS.iter().zip(hist.bucket_counts) {
+            out.push_str(&format!(
+                "heliotorrent_upstream_fetch_duration_milliseconds_bucket{{log=\"{name}\",le=\"{bound}\"}} {count}\n"
+            ));
+        }
+        out.push_str(&format!(
+            "heliotorrent_upstream_fetch_duration_milliseconds_bucket{{log=\"{name}\",le=\"+Inf\"}} {}\n",
+            hist.count
+        ));
+        out.push_str(&format!(
+            "heliotorrent_upstream_fetch_duration_milliseconds_sum{{log=\"{name}\"}} {}\n",
+            hist.sum_ms
+        ));
+        out.push_str(&format!(
+            "heliotorrent_upstream_fetch_duration_milliseconds_count{{log=\"{name}\"}} {}\n",
+            hist.count
+        ));
+    }
+
+    out
+}
+
+/// Records the outcome of an upstream fetch (tile or byte range) against
+/// `name`'s counters, for the `/metrics` export.
+pub async fn record_upstream_fetch(stats: &StatsMap, name: &str, elapsed: Duration, success: bool) {
+    let mut stats_guard = stats.lock().await;
+    let log_stats = stats_guard.entry(name.to_string()).or_default();
+    log_stats.upstream_fetch_latency.observe(elapsed);
+    if !success {
+        log_stats.upstream_errors += 1;
+    }
+}
+
+/// Periodically POSTs the same text [`render`] produces to a
+/// Pushgateway-compatible collector endpoint, for deployments where the
+/// collector can't reach back in to scrape `/metrics` itself.
+pub async fn run_push_exporter(
+    stats: StatsMap,
+    client: reqwest::Client,
+    push_url: String,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let body = {
+            let stats_guard = stats.lock().await;
+            render(&stats_guard)
+        };
+        if let Err(e) = client.post(&push_url).body(body).send().await {
+            warn!("Failed to push metrics to {}: {}", push_url, e);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! PROXY protocol (v1 and v2) termination.
+//!
+//! When `Config::proxy_protocol` is enabled, every accepted TCP connection is
+//! expected to begin with a PROXY protocol header describing the real client
+//! address before the actual payload (TLS handshake / HTTP request) begins.
+//! We peek the header off the stream, decode it, and hand back the true
+//! source `SocketAddr` alongside a stream with the header bytes consumed.
+
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+
+use axum_server::accept::Accept;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tower::Layer;
+use tower_http::add_extension::{AddExtension, AddExtensionLayer};
+use tracing::{debug, warn};
+
+/// The real client address, recovered from a PROXY protocol header (or the
+/// TCP peer address if the mode is disabled), injected as a request
+/// extension so handlers see it instead of the load balancer's address.
+#[derive(Debug, Clone, Copy)]
+pub struct RealClientAddr(pub SocketAddr);
+
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+#[derive(Debug)]
+pub enum ProxyProtocolError {
+    Io(std::io::Error),
+    Malformed(&'static str),
+}
+
+impl std::fmt::Display for ProxyProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProxyProtocolError::Io(e) => write!(f, "I/O error reading PROXY header: {e}"),
+            ProxyProtocolError::Malformed(msg) => write!(f, "malformed PROXY header: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ProxyProtocolError {}
+
+impl From<std::io::Error> for ProxyProtocolError {
+    fn from(e: std::io::Error) -> Self {
+        ProxyProtocolError::Io(e)
+    }
+}
+
+/// Reads and strips a PROXY protocol header (v1 or v2) from the front of
+/// `stream`, returning the real client address it describes.
+///
+/// Unrecognized or "UNKNOWN" headers produce a `Malformed` error; callers
+/// should treat that as fatal for the connection rather than falling through
+/// to the original peer address, since silently trusting an unverified
+/// address would defeat the point of enabling this mode.
+pub async fn read_proxy_header(
+    stream: &mut TcpStream,
+) -> Result<SocketAddr, ProxyProtocolError> {
+    let mut prefix = [0u8; 12];
+    stream.read_exact(&mut prefix).await?;
+
+    if prefix == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if &prefix[..5] == b"PROXY" {
+        read_v1(stream, &prefix).await
+    } else {
+        Err(ProxyProtocolError::Malformed(
+            "missing PROXY protocol signature",
+        ))
+    }
+}
+
+async fn read_v1(
+    stream: &mut TcpStream,
+    prefix: &[u8; 12],
+) -> Result<SocketAddr, ProxyProtocolError> {
+    // We've already consumed 12 bytes; read the rest of the CRLF-terminated
+    // line (v1 headers are capped at 107 bytes total per the spec).
+    let mut line = prefix.to_vec();
+    let mut byte = [0u8; 1];
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= 107 {
+            return Err(ProxyProtocolError::Malformed("v1 header too long"));
+        }
+        stream.read_exact(&mut byte).await?;
+        line.push(byte[0]);
+    }
+
+    let text = std::str::from_utf8(&line)
+        .map_err(|_| ProxyProtocolError::Malformed("v1 header not UTF-8"))?
+        .trim_end();
+    let fields: Vec<&str> = text.split(' ').collect();
+    debug!(header = text, "Parsed PROXY v1 header");
+
+    match fields.as_slice() {
+        ["PROXY", "TCP4" | "TCP6", src_ip, _dst_ip, src_port, _dst_port] => {
+            let ip: IpAddr = src_ip
+                .parse()
+                .map_err(|_| ProxyProtocolError::Malformed("invalid v1 source address"))?;
+            let port: u16 = src_port
+                .parse()
+                .map_err(|_| ProxyProtocolError::Malformed("invalid v1 source port"))?;
+            Ok(SocketAddr::new(ip, port))
+        }
+        ["PROXY", "UNKNOWN", ..] => Err(ProxyProtocolError::Malformed("UNKNOWN proxied address")),
+        _ => Err(ProxyProtocolError::Malformed("unrecognized v1 header")),
+    }
+}
+
+async fn read_v2(stream: &mut TcpStream) -> Result<SocketAddr, ProxyProtocolError> {
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let version_command = header[0];
+    let version = version_command >> 4;
+    let command = version_command & 0x0F;
+    if version != 2 {
+        return Err(ProxyProtocolError::Malformed("unsupported v2 version"));
+    }
+
+    let address_family = header[1] >> 4;
+    let transport = header[1] & 0x0F;
+    let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).await?;
+
+    if command == 0x0 {
+        // LOCAL command: connection from a health check / proxy itself, no
+        // address to recover. Reject rather than guessing.
+        return Err(ProxyProtocolError::Malformed(
+            "LOCAL command carries no client address",
+        ));
+    }
+    if transport != 0x1 {
+        // Only STREAM (TCP) is meaningful for us.
+        return Err(ProxyProtocolError::Malformed("unsupported v2 transport"));
+    }
+
+    match address_family {
+        0x1 => {
+            if body.len() < 12 {
+                return Err(ProxyProtocolError::Malformed("v2 IPv4 body too short"));
+            }
+            let src_ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let src_port = u16::from_be_bytes([body[8], body[9]]);
+            Ok(SocketAddr::new(IpAddr::V4(src_ip), src_port))
+        }
+        0x2 => {
+            if body.len() < 36 {
+                return Err(ProxyProtocolError::Malformed("v2 IPv6 body too short"));
+            }
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&body[0..16]);
+            let src_ip = Ipv6Addr::from(octets);
+            let src_port = u16::from_be_bytes([body[32], body[33]]);
+            Ok(SocketAddr::new(IpAddr::V6(src_ip), src_port))
+        }
+        _ => Err(ProxyProtocolError::Malformed(
+            "unsupported v2 address family",
+        )),
+    }
+}
+
+/// Closes `stream` with a best-effort notice; used when a PROXY header is
+/// required but missing or malformed, since we can no longer trust anything
+/// else read from the connection.
+pub async fn reject(mut stream: TcpStream) {
+    let _ = stream.shutdown().await;
+}
+
+/// An [`axum_server::accept::Accept`] that, when enabled, strips a PROXY
+/// protocol header off each accepted connection before it reaches axum (or,
+/// for HTTPS, before it reaches the TLS handshake), and injects the real
+/// client address as a `RealClientAddr` request extension.
+#[derive(Clone, Copy, Default)]
+pub struct ProxyProtocolAcceptor {
+    enabled: bool,
+}
+
+impl ProxyProtocolAcceptor {
+    pub fn new(enabled: bool) -> Self {
+        ProxyProtocolAcceptor { enabled }
+    }
+}
+
+impl<S> Accept<TcpStream, S> for ProxyProtocolAcceptor
+where
+    S: Send + 'static,
+{
+    type Stream = TcpStream;
+    type Service = AddExtension<S, RealClientAddr>;
+    type Future =
+        Pin<Box<dyn Future<Output = std::io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, mut stream: TcpStream, service: S) -> Self::Future {
+        let enabled = self.enabled;
+        Box::pin(async move {
+            let addr = if enabled {
+                match read_proxy_header(&mut stream).await {
+                    Ok(addr) => addr,
+                    Err(e) => {
+                        warn!("Rejecting connection with invalid PROXY header: {}", e);
+                        reject(stream).await;
+                        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+                    }
+                }
+            } else {
+                stream.peer_addr()?
+            };
+
+            let service = AddExtensionLayer::new(RealClientAddr(addr)).layer(service);
+            Ok((stream, service))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// `read_proxy_header` reads off a real `TcpStream`, so tests need an
+    /// actual connected socket pair rather than an in-memory duplex.
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, (server, _)) =
+            tokio::join!(TcpStream::connect(addr), listener.accept());
+        (client.unwrap(), server.unwrap())
+    }
+
+    #[tokio::test]
+    async fn v1_tcp4_header_parses_source_address() {
+        let (mut client, mut server) = connected_pair().await;
+        client
+            .write_all(b"PROXY TCP4 203.0.113.7 198.51.100.1 56324 443\r\n")
+            .await
+            .unwrap();
+        let addr = read_proxy_header(&mut server).await.unwrap();
+        assert_eq!(addr, "203.0.113.7:56324".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn v1_unknown_is_rejected() {
+        let (mut client, mut server) = connected_pair().await;
+        client.write_all(b"PROXY UNKNOWN\r\n").await.unwrap();
+        assert!(read_proxy_header(&mut server).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_ipv4_header_parses_source_address() {
+        let (mut client, mut server) = connected_pair().await;
+        let mut packet = V2_SIGNATURE.to_vec();
+        packet.push(0x21); // version 2, command PROXY
+        packet.push(0x11); // AF_INET, STREAM
+        let mut body = Vec::new();
+        body.extend_from_slice(&[203, 0, 113, 7]); // src ip
+        body.extend_from_slice(&[198, 51, 100, 1]); // dst ip
+        body.extend_from_slice(&56324u16.to_be_bytes()); // src port
+        body.extend_from_slice(&443u16.to_be_bytes()); // dst port
+        packet.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&body);
+
+        client.write_all(&packet).await.unwrap();
+        let addr = read_proxy_header(&mut server).await.unwrap();
+        assert_eq!(addr, "203.0.113.7:56324".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn v2_local_command_is_rejected() {
+        let (mut client, mut server) = connected_pair().await;
+        let mut packet = V2_SIGNATURE.to_vec();
+        packet.push(0x20); // version 2, command LOCAL
+        packet.push(0x00);
+        packet.extend_from_slice(&0u16.to_be_bytes());
+
+        client.write_all(&packet).await.unwrap();
+        assert!(read_proxy_header(&mut server).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_signature_is_rejected() {
+        let (mut client, mut server) = connected_pair().await;
+        client.write_all(b"not a proxy header!!").await.unwrap();
+        assert!(read_proxy_header(&mut server).await.is_err());
+    }
+}
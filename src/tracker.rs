@@ -0,0 +1,666 @@
+//! Built-in BitTorrent tracker for the torrents we serve under `/webseed`.
+//!
+//! Implements the UDP tracker protocol (BEP-15) plus an HTTP announce/scrape
+//! pair (BEP-3/BEP-48) on top of the same swarm table, so peers can discover
+//! each other without relying on a third-party tracker.
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::{Extension, RawQuery, State};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use reqwest::StatusCode;
+use sha1::{Digest, Sha1};
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+pub type InfoHash = [u8; 20];
+
+/// How long a UDP connection id stays valid for (BEP-15 recommends ~2 minutes
+/// to limit the value of a spoofed source address).
+const CONNECTION_ID_TTL: Duration = Duration::from_secs(120);
+/// Peers that haven't announced in this long are dropped from the swarm.
+const PEER_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// How often we re-scan `torrent_dir` for the set of info_hashes we'll track.
+const TORRENT_DIR_RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+const ACTION_CONNECT: u32 = 0;
+const ACTION_ANNOUNCE: u32 = 1;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+const PROTOCOL_ID: u64 = 0x41727101980;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PeerKind {
+    Seeder,
+    Leecher,
+}
+
+struct Peer {
+    addr: SocketAddr,
+    kind: PeerKind,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+struct Swarm {
+    peers: HashMap<[u8; 20], Peer>,
+    completed: u64,
+}
+
+impl Swarm {
+    fn seeders(&self) -> usize {
+        self.peers
+            .values()
+            .filter(|p| p.kind == PeerKind::Seeder)
+            .count()
+    }
+
+    fn leechers(&self) -> usize {
+        self.peers
+            .values()
+            .filter(|p| p.kind == PeerKind::Leecher)
+            .count()
+    }
+
+    fn reap(&mut self, now: Instant) {
+        self.peers
+            .retain(|_, peer| now.duration_since(peer.last_seen) < PEER_TIMEOUT);
+    }
+}
+
+type SwarmTable = Arc<Mutex<HashMap<InfoHash, Swarm>>>;
+type ConnectionTable = Arc<Mutex<HashMap<u64, Instant>>>;
+
+#[derive(Clone)]
+pub struct TrackerState {
+    swarms: SwarmTable,
+    connections: ConnectionTable,
+    allowed_info_hashes: Arc<Mutex<HashSet<InfoHash>>>,
+    torrent_dir: PathBuf,
+    announce_interval_secs: u32,
+}
+
+impl TrackerState {
+    pub fn new(torrent_dir: PathBuf) -> Self {
+        TrackerState {
+            swarms: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            allowed_info_hashes: Arc::new(Mutex::new(HashSet::new())),
+            torrent_dir,
+            announce_interval_secs: 1800,
+        }
+    }
+
+    async fn is_allowed(&self, info_hash: &InfoHash) -> bool {
+        self.allowed_info_hashes.lock().await.contains(info_hash)
+    }
+
+    /// Background task: periodically rescans `torrent_dir` for `.torrent`
+    /// files and recomputes the set of info_hashes we'll accept announces
+    /// for, and reaps stale peers / expired connection ids.
+    pub async fn run_maintenance(self: Arc<Self>) {
+        loop {
+            match scan_torrent_dir(&self.torrent_dir).await {
+                Ok(hashes) => {
+                    let count = hashes.len();
+                    *self.allowed_info_hashes.lock().await = hashes;
+                    debug!(count, "Rescanned torrent_dir for tracked info_hashes");
+                }
+                Err(e) => error!("Failed to scan torrent_dir for tracker: {}", e),
+            }
+
+            let now = Instant::now();
+            {
+                let mut swarms = self.swarms.lock().await;
+                for swarm in swarms.values_mut() {
+                    swarm.reap(now);
+                }
+                swarms.retain(|_, swarm| !swarm.peers.is_empty());
+            }
+            {
+                let mut connections = self.connections.lock().await;
+                connections.retain(|_, created| now.duration_since(*created) < CONNECTION_ID_TTL);
+            }
+
+            tokio::time::sleep(TORRENT_DIR_RESCAN_INTERVAL).await;
+        }
+    }
+}
+
+async fn scan_torrent_dir(torrent_dir: &Path) -> std::io::Result<HashSet<InfoHash>> {
+    let mut hashes = HashSet::new();
+    let mut stack = vec![torrent_dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "torrent") {
+                match tokio::fs::read(&path).await {
+                    Ok(bytes) => match info_hash_of_torrent(&bytes) {
+                        Some(hash) => {
+                            hashes.insert(hash);
+                        }
+                        None => warn!("Could not extract info_hash from {}", path.display()),
+                    },
+                    Err(e) => error!("Failed to read {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+/// Computes the SHA-1 info_hash of a `.torrent` file by locating the raw
+/// bencoded bytes of its top-level `info` dict and hashing them directly,
+/// without needing a full bencode decoder.
+fn info_hash_of_torrent(data: &[u8]) -> Option<InfoHash> {
+    let (start, end) = find_bencode_dict_value(data, 0, b"info")?;
+    let mut hasher = Sha1::new();
+    hasher.update(&data[start..end]);
+    Some(hasher.finalize().into())
+}
+
+/// Scans the bencoded dict starting at `data[dict_start]` for `key`, and
+/// returns the byte span of its (still-encoded) value.
+fn find_bencode_dict_value(data: &[u8], dict_start: usize, key: &[u8]) -> Option<(usize, usize)> {
+    if data.get(dict_start) != Some(&b'd') {
+        return None;
+    }
+    let mut i = dict_start + 1;
+    loop {
+        if data.get(i) == Some(&b'e') {
+            return None;
+        }
+        let (k, after_key) = parse_bencode_string(data, i)?;
+        let value_end = skip_bencode_value(data, after_key)?;
+        if k == key {
+            return Some((after_key, value_end));
+        }
+        i = value_end;
+    }
+}
+
+fn parse_bencode_string(data: &[u8], i: usize) -> Option<(&[u8], usize)> {
+    let colon = data[i..].iter().position(|&b| b == b':')? + i;
+    let len: usize = std::str::from_utf8(&data[i..colon]).ok()?.parse().ok()?;
+    let start = colon + 1;
+    let end = start.checked_add(len)?;
+    Some((data.get(start..end)?, end))
+}
+
+fn skip_bencode_value(data: &[u8], i: usize) -> Option<usize> {
+    match *data.get(i)? {
+        b'i' => {
+            let end = data[i..].iter().position(|&b| b == b'e')? + i;
+            Some(end + 1)
+        }
+        b'l' => {
+            let mut j = i + 1;
+            while data.get(j) != Some(&b'e') {
+                j = skip_bencode_value(data, j)?;
+            }
+            Some(j + 1)
+        }
+        b'd' => {
+            let mut j = i + 1;
+            while data.get(j) != Some(&b'e') {
+                let (_, after_key) = parse_bencode_string(data, j)?;
+                j = skip_bencode_value(data, after_key)?;
+            }
+            Some(j + 1)
+        }
+        b'0'..=b'9' => parse_bencode_string(data, i).map(|(_, end)| end),
+        _ => None,
+    }
+}
+
+/// Runs the UDP tracker accept loop forever (until the socket errors).
+pub async fn run_udp_tracker(addr: SocketAddr, state: Arc<TrackerState>) -> std::io::Result<()> {
+    let socket = Arc::new(UdpSocket::bind(addr).await?);
+    info!("Starting UDP BitTorrent tracker on {}.", addr);
+
+    let mut buf = vec![0u8; 2048];
+    loop {
+        let (len, peer_addr) = socket.recv_from(&mut buf).await?;
+        let packet = buf[..len].to_vec();
+        let socket = socket.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Some(response) = handle_udp_packet(&state, &packet, peer_addr).await
+                && let Err(e) = socket.send_to(&response, peer_addr).await
+            {
+                warn!("Failed to send UDP tracker response to {}: {}", peer_addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_udp_packet(
+    state: &Arc<TrackerState>,
+    packet: &[u8],
+    peer_addr: SocketAddr,
+) -> Option<Vec<u8>> {
+    if packet.len() < 16 {
+        return None;
+    }
+    let action = u32::from_be_bytes(packet[8..12].try_into().ok()?);
+    let transaction_id = u32::from_be_bytes(packet[12..16].try_into().ok()?);
+
+    match action {
+        ACTION_CONNECT => {
+            let protocol_id = u64::from_be_bytes(packet[0..8].try_into().ok()?);
+            if protocol_id != PROTOCOL_ID {
+                return None;
+            }
+            let connection_id = rand::random::<u64>();
+            state
+                .connections
+                .lock()
+                .await
+                .insert(connection_id, Instant::now());
+
+            let mut resp = Vec::with_capacity(16);
+            resp.extend_from_slice(&ACTION_CONNECT.to_be_bytes());
+            resp.extend_from_slice(&transaction_id.to_be_bytes());
+            resp.extend_from_slice(&connection_id.to_be_bytes());
+            Some(resp)
+        }
+        ACTION_ANNOUNCE => {
+            if packet.len() < 98 {
+                return Some(error_response(transaction_id, "malformed announce"));
+            }
+            let connection_id = u64::from_be_bytes(packet[0..8].try_into().ok()?);
+            if !valid_connection(state, connection_id).await {
+                return Some(error_response(transaction_id, "invalid connection id"));
+            }
+            let info_hash: InfoHash = packet[16..36].try_into().ok()?;
+            if !state.is_allowed(&info_hash).await {
+                return Some(error_response(transaction_id, "unknown info_hash"));
+            }
+            let peer_id: [u8; 20] = packet[36..56].try_into().ok()?;
+            // Layout per BEP-15: downloaded@56, left@64, uploaded@72,
+            // event@80, ip@84, key@88, num_want@92, port@96. `event`
+            // (0=none, 1=completed, 2=started, 3=stopped) isn't used to
+            // compute a response here, but must still be skipped over
+            // rather than mistaken for the IP-override field that follows it.
+            let left = u64::from_be_bytes(packet[64..72].try_into().ok()?);
+            let ip_field = u32::from_be_bytes(packet[84..88].try_into().ok()?);
+            let port = u16::from_be_bytes(packet[96..98].try_into().ok()?);
+
+            let announced_addr = if ip_field != 0 {
+                SocketAddr::new(std::net::IpAddr::V4(ip_field.into()), port)
+            } else {
+                SocketAddr::new(peer_addr.ip(), port)
+            };
+
+            let (seeders, leechers, peers) =
+                record_announce(state, info_hash, peer_id, announced_addr, left).await;
+
+            let mut resp = Vec::new();
+            resp.extend_from_slice(&ACTION_ANNOUNCE.to_be_bytes());
+            resp.extend_from_slice(&transaction_id.to_be_bytes());
+            resp.extend_from_slice(&state.announce_interval_secs.to_be_bytes());
+            resp.extend_from_slice(&(leechers as u32).to_be_bytes());
+            resp.extend_from_slice(&(seeders as u32).to_be_bytes());
+            for peer in peers {
+                if let std::net::IpAddr::V4(ip) = peer.ip() {
+                    resp.extend_from_slice(&ip.octets());
+                    resp.extend_from_slice(&peer.port().to_be_bytes());
+                }
+            }
+            Some(resp)
+        }
+        ACTION_SCRAPE => {
+            let connection_id = u64::from_be_bytes(packet[0..8].try_into().ok()?);
+            if !valid_connection(state, connection_id).await {
+                return Some(error_response(transaction_id, "invalid connection id"));
+            }
+            let mut resp = Vec::new();
+            resp.extend_from_slice(&ACTION_SCRAPE.to_be_bytes());
+            resp.extend_from_slice(&transaction_id.to_be_bytes());
+
+            let hashes = packet[16..].chunks_exact(20);
+            let swarms = state.swarms.lock().await;
+            for chunk in hashes {
+                let info_hash: InfoHash = chunk.try_into().ok()?;
+                let (seeders, completed, leechers) = swarms
+                    .get(&info_hash)
+                    .map(|s| (s.seeders() as u32, s.completed as u32, s.leechers() as u32))
+                    .unwrap_or((0, 0, 0));
+                resp.extend_from_slice(&seeders.to_be_bytes());
+                resp.extend_from_slice(&completed.to_be_bytes());
+                resp.extend_from_slice(&leechers.to_be_bytes());
+            }
+            Some(resp)
+        }
+        _ => Some(error_response(transaction_id, "unknown action")),
+    }
+}
+
+async fn valid_connection(state: &Arc<TrackerState>, connection_id: u64) -> bool {
+    match state.connections.lock().await.get(&connection_id) {
+        Some(created) => created.elapsed() < CONNECTION_ID_TTL,
+        None => false,
+    }
+}
+
+fn error_response(transaction_id: u32, message: &str) -> Vec<u8> {
+    let mut resp = Vec::new();
+    resp.extend_from_slice(&ACTION_ERROR.to_be_bytes());
+    resp.extend_from_slice(&transaction_id.to_be_bytes());
+    resp.extend_from_slice(message.as_bytes());
+    resp
+}
+
+/// Inserts/updates a peer in the swarm for `info_hash` and returns the
+/// current (seeders, leechers, peer addresses to hand back).
+async fn record_announce(
+    state: &Arc<TrackerState>,
+    info_hash: InfoHash,
+    peer_id: [u8; 20],
+    addr: SocketAddr,
+    left: u64,
+) -> (usize, usize, Vec<SocketAddr>) {
+    let kind = if left == 0 {
+        PeerKind::Seeder
+    } else {
+        PeerKind::Leecher
+    };
+
+    let mut swarms = state.swarms.lock().await;
+    let swarm = swarms.entry(info_hash).or_default();
+    let was_leecher = swarm
+        .peers
+        .get(&peer_id)
+        .is_some_and(|p| p.kind == PeerKind::Leecher);
+    if was_leecher && kind == PeerKind::Seeder {
+        swarm.completed += 1;
+    }
+    swarm.peers.insert(
+        peer_id,
+        Peer {
+            addr,
+            kind,
+            last_seen: Instant::now(),
+        },
+    );
+
+    let peers = swarm
+        .peers
+        .iter()
+        .filter(|(id, _)| **id != peer_id)
+        .map(|(_, p)| p.addr)
+        .take(50)
+        .collect();
+
+    (swarm.seeders(), swarm.leechers(), peers)
+}
+
+/// Mirrors the UDP tracker on top of HTTP, per BEP-3/BEP-48, so clients that
+/// only speak HTTP trackers can still join the swarm.
+pub fn http_router(state: Arc<TrackerState>) -> Router {
+    Router::new()
+        .route("/announce", get(http_announce))
+        .route("/scrape", get(http_scrape))
+        .with_state(state)
+}
+
+/// Percent-decodes a single `application/x-www-form-urlencoded` query value
+/// into raw bytes. `info_hash` and `peer_id` are essentially random 20-byte
+/// binary, not text, so they must be decoded this way rather than through
+/// `axum`'s `Query` extractor: that goes through `form_urlencoded`, which
+/// treats the decoded bytes as UTF-8 and lossily replaces anything that
+/// isn't with U+FFFD, corrupting almost every real-world info_hash/peer_id
+/// before application code ever sees it.
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 3;
+                }
+                None => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            },
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Parses a raw (still percent-encoded) query string into key/value pairs,
+/// decoding each value to raw bytes and preserving duplicate keys (`scrape`
+/// repeats `info_hash` once per requested torrent).
+fn parse_raw_query(query: &str) -> Vec<(String, Vec<u8>)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) => (String::from_utf8_lossy(&percent_decode(k)).into_owned(), percent_decode(v)),
+            None => (String::from_utf8_lossy(&percent_decode(pair)).into_owned(), Vec::new()),
+        })
+        .collect()
+}
+
+fn find_query_value<'a>(pairs: &'a [(String, Vec<u8>)], key: &str) -> Option<&'a [u8]> {
+    pairs
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_slice())
+}
+
+fn find_query_str<'a>(pairs: &'a [(String, Vec<u8>)], key: &str) -> Option<&'a str> {
+    find_query_value(pairs, key).and_then(|v| std::str::from_utf8(v).ok())
+}
+
+fn decode_info_hash(raw: &[u8]) -> Option<InfoHash> {
+    raw.try_into().ok()
+}
+
+async fn http_announce(
+    State(state): State<Arc<TrackerState>>,
+    // The PROXY protocol acceptor only wraps the plain TCP/TLS listeners, so
+    // HTTP/3 (QUIC) requests never carry this extension (see h3::handle_request);
+    // fall back to the unspecified address for those rather than serving a 500.
+    client_addr: Option<Extension<crate::proxy_protocol::RealClientAddr>>,
+    RawQuery(query): RawQuery,
+) -> axum::response::Response {
+    let remote_ip = client_addr
+        .map(|Extension(addr)| addr.0.ip())
+        .unwrap_or(IpAddr::from(Ipv6Addr::UNSPECIFIED));
+    let pairs = parse_raw_query(query.as_deref().unwrap_or(""));
+
+    let Some(info_hash) = find_query_value(&pairs, "info_hash").and_then(decode_info_hash) else {
+        return (StatusCode::BAD_REQUEST, "invalid info_hash").into_response();
+    };
+    if !state.is_allowed(&info_hash).await {
+        return (StatusCode::NOT_FOUND, "unknown info_hash").into_response();
+    }
+    let Some(peer_id) = find_query_value(&pairs, "peer_id").and_then(decode_info_hash) else {
+        return (StatusCode::BAD_REQUEST, "invalid peer_id").into_response();
+    };
+    let Some(port) = find_query_str(&pairs, "port").and_then(|v| v.parse::<u16>().ok()) else {
+        return (StatusCode::BAD_REQUEST, "invalid port").into_response();
+    };
+    let Some(left) = find_query_str(&pairs, "left").and_then(|v| v.parse::<u64>().ok()) else {
+        return (StatusCode::BAD_REQUEST, "invalid left").into_response();
+    };
+
+    let addr = SocketAddr::new(remote_ip, port);
+    let (seeders, leechers, peers) = record_announce(&state, info_hash, peer_id, addr, left).await;
+
+    let mut compact_peers = Vec::with_capacity(peers.len() * 6);
+    for peer in peers {
+        if let std::net::IpAddr::V4(ip) = peer.ip() {
+            compact_peers.extend_from_slice(&ip.octets());
+            compact_peers.extend_from_slice(&peer.port().to_be_bytes());
+        }
+    }
+
+    let mut body = Vec::new();
+    body.extend_from_slice(b"d8:intervali");
+    body.extend_from_slice(state.announce_interval_secs.to_string().as_bytes());
+    body.extend_from_slice(b"e8:completei");
+    body.extend_from_slice(seeders.to_string().as_bytes());
+    body.extend_from_slice(b"e10:incompletei");
+    body.extend_from_slice(leechers.to_string().as_bytes());
+    body.extend_from_slice(b"e5:peers");
+    body.extend_from_slice(compact_peers.len().to_string().as_bytes());
+    body.push(b':');
+    body.extend_from_slice(&compact_peers);
+    body.push(b'e');
+
+    (StatusCode::OK, body).into_response()
+}
+
+async fn http_scrape(
+    State(state): State<Arc<TrackerState>>,
+    RawQuery(query): RawQuery,
+) -> axum::response::Response {
+    let pairs = parse_raw_query(query.as_deref().unwrap_or(""));
+    let requested: Vec<InfoHash> = pairs
+        .iter()
+        .filter(|(k, _)| k == "info_hash")
+        .filter_map(|(_, v)| decode_info_hash(v))
+        .collect();
+    if requested.is_empty() {
+        return (StatusCode::BAD_REQUEST, "no info_hash provided").into_response();
+    }
+
+    let swarms = state.swarms.lock().await;
+    let mut body = Vec::new();
+    body.extend_from_slice(b"d5:filesd");
+    for info_hash in requested {
+        let (seeders, completed, leechers) = swarms
+            .get(&info_hash)
+            .map(|s| (s.seeders() as u32, s.completed as u32, s.leechers() as u32))
+            .unwrap_or((0, 0, 0));
+        body.push(b'2');
+        body.push(b'0');
+        body.push(b':');
+        body.extend_from_slice(&info_hash);
+        body.extend_from_slice(b"d8:completei");
+        body.extend_from_slice(seeders.to_string().as_bytes());
+        body.extend_from_slice(b"e10:downloadedi");
+        body.extend_from_slice(completed.to_string().as_bytes());
+        body.extend_from_slice(b"e10:incompletei");
+        body.extend_from_slice(leechers.to_string().as_bytes());
+        body.push(b'e');
+    }
+    body.extend_from_slice(b"ee");
+
+    (StatusCode::OK, body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn state_with_connection_and_hash(info_hash: InfoHash) -> (Arc<TrackerState>, u64) {
+        let state = Arc::new(TrackerState::new(PathBuf::from(".")));
+        state.allowed_info_hashes.lock().await.insert(info_hash);
+        let connection_id = 42u64;
+        state
+            .connections
+            .lock()
+            .await
+            .insert(connection_id, Instant::now());
+        (state, connection_id)
+    }
+
+    /// Builds a spec-correct BEP-15 announce packet: connection_id@0,
+    /// action@8, transaction_id@12, info_hash@16, peer_id@36, downloaded@56,
+    /// left@64, uploaded@72, event@80, ip@84, key@88, num_want@92, port@96.
+    fn build_announce_packet(
+        connection_id: u64,
+        transaction_id: u32,
+        info_hash: InfoHash,
+        peer_id: [u8; 20],
+        left: u64,
+        event: u32,
+        ip_override: u32,
+        port: u16,
+    ) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&connection_id.to_be_bytes());
+        packet.extend_from_slice(&ACTION_ANNOUNCE.to_be_bytes());
+        packet.extend_from_slice(&transaction_id.to_be_bytes());
+        packet.extend_from_slice(&info_hash);
+        packet.extend_from_slice(&peer_id);
+        packet.extend_from_slice(&0u64.to_be_bytes()); // downloaded
+        packet.extend_from_slice(&left.to_be_bytes());
+        packet.extend_from_slice(&0u64.to_be_bytes()); // uploaded
+        packet.extend_from_slice(&event.to_be_bytes());
+        packet.extend_from_slice(&ip_override.to_be_bytes());
+        packet.extend_from_slice(&0u32.to_be_bytes()); // key
+        packet.extend_from_slice(&(-1i32).to_be_bytes()); // num_want
+        packet.extend_from_slice(&port.to_be_bytes());
+        packet
+    }
+
+    #[tokio::test]
+    async fn announce_packet_layout_parses_left_and_does_not_mistake_event_for_ip() {
+        let info_hash = [0x11; 20];
+        let peer_id = [0x22; 20];
+        let (state, connection_id) = state_with_connection_and_hash(info_hash).await;
+
+        // A "completed" announce (event=1, the BEP-15 code for it) with no
+        // IP override: every field must be read from its own offset, not
+        // from a window straddling two of them, and `event` must not be
+        // mistaken for the IP-override field that follows it.
+        let packet = build_announce_packet(
+            connection_id,
+            7,
+            info_hash,
+            peer_id,
+            /* left */ 123_456,
+            /* event */ 1,
+            /* ip_override */ 0,
+            6881,
+        );
+
+        let peer_addr: SocketAddr = "203.0.113.9:54321".parse().unwrap();
+        let response = handle_udp_packet(&state, &packet, peer_addr)
+            .await
+            .expect("valid announce should produce a response");
+
+        // Response layout: action@0, transaction_id@4, interval@8,
+        // leechers@12, seeders@16. A `left` read shifted onto `uploaded`
+        // (all zero here) would misclassify this leecher as a seeder.
+        assert_eq!(u32::from_be_bytes(response[12..16].try_into().unwrap()), 1);
+        assert_eq!(u32::from_be_bytes(response[16..20].try_into().unwrap()), 0);
+
+        let swarms = state.swarms.lock().await;
+        let swarm = swarms.get(&info_hash).unwrap();
+        let peer = swarm.peers.get(&peer_id).unwrap();
+        // With `ip_field` reading `event` (1) instead of the real IP-override
+        // field, the announced address would become `0.0.0.1:<port>`. The
+        // IP comes from the UDP source, the port from the announce itself.
+        assert_eq!(peer.addr, SocketAddr::new(peer_addr.ip(), 6881));
+    }
+}